@@ -1,20 +1,45 @@
 use atom_syndication::Feed;
+use base64::Engine;
+use chrono::Utc;
 use quick_xml::{self, events};
 use ratatui::widgets::ListState;
 use rss::Channel;
 use serde::{Deserialize, Serialize};
-use serde_json;
 use std::{
+    collections::{BTreeMap, HashMap, HashSet},
     env,
     error::Error,
     fs::{self, File},
-    io::Write,
+    io::{self, Write},
+    path::Path,
+    process::{Command, Stdio},
+    sync::LazyLock,
+    time::Duration,
 };
+use tokio::task::JoinSet;
 
-use crate::feed;
+use crate::{
+    feed,
+    images::Images,
+    keymap::{Context, Keymap},
+    layout::{Layout, LayoutConfig},
+    messages::Messages,
+    query::Query,
+    readability,
+    retention::{self, Retention},
+    rules::{self, Rule, RuleConfig},
+    session::{self, Session, View},
+    store::{
+        self, Backend, Paths, Store,
+        json::{self, JsonStore},
+    },
+    styling::ArticleCache,
+    sync::{self, SyncConfig},
+    theme::{Theme, ThemeConfig, ThemeName},
+};
 
 pub enum FeedType {
-    RSS,
+    Rss,
     Atom,
 }
 
@@ -25,139 +50,1352 @@ pub enum Screen {
     Exiting,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Default)]
 pub struct Index {
     pub meta: Vec<feed::Metadata>,
-    pub state: ListState,
 }
 
 impl Index {
-    pub fn save(&self) -> Result<(), Box<dyn Error>> {
-        let serialized = serde_json::to_string(&self).unwrap();
-        let mut path = env::home_dir().unwrap();
-        path.push(".russ/index.json");
-        let mut file = File::create(path)?;
-        file.write_all(serialized.as_ref())?;
+    /// Writes the index atomically, first copying the current file to
+    /// `index.json.bak` if it is still readable.
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let serialized = json::to_string(self)?;
+
+        if let Ok(current) = fs::read_to_string(path)
+            && json::from_str::<Index>(&current).is_ok()
+        {
+            store::write_atomic(&path.with_extension("json.bak"), current.as_bytes())?;
+        }
+        store::write_atomic(path, serialized.as_bytes())?;
 
         Ok(())
     }
 
-    pub fn from_file() -> Result<Index, Box<dyn Error>> {
-        let mut path = env::home_dir().unwrap();
-        path.push(".russ/index.json");
+    /// Reads the index, falling back to the backup if the main file is
+    /// damaged.
+    pub fn from_file(path: &Path) -> Result<Index, Box<dyn Error>> {
         let content = fs::read_to_string(path)?;
-        Ok(serde_json::from_str(&content)?)
+        match json::from_str(&content) {
+            Ok(index) => Ok(index),
+            Err(e) => {
+                let backup = fs::read_to_string(path.with_extension("json.bak"))
+                    .map_err(|_| format!("index.json is damaged ({e}) and has no backup"))?;
+                eprintln!("index.json is damaged ({e}), using index.json.bak");
+                json::from_str(&backup)
+            }
+        }
+    }
+
+    /// Adds the feeds another machine's copy of the index has that this one
+    /// lacks. Returns `(id here, id there)` for feeds both have under
+    /// different ids, so their posts can be merged as well.
+    pub fn merge(&mut self, other: Index) -> Vec<(String, String)> {
+        let mut renamed = Vec::new();
+        for meta in other.meta {
+            match self.meta.iter().find(|m| m.same_feed(&meta)) {
+                Some(m) if m.id != meta.id => renamed.push((m.id.clone(), meta.id)),
+                Some(_) => {}
+                None => self.meta.push(meta),
+            }
+        }
+        renamed
     }
 }
 
 #[derive(Deserialize)]
+#[serde(default)]
 pub struct Config {
+    /// Directory holding the index, database and session. Everything in it
+    /// can be synced between machines.
+    pub data_dir: String,
+    /// Directory for the per-feed JSON files, relative to `data_dir`.
     pub feed_dir: String,
+    #[allow(dead_code)]
     pub config_dir: String,
-    pub feeds: Vec<String>,
+    pub storage: Backend,
+    pub feeds: Vec<FeedConfig>,
+    pub retention: Retention,
+    pub smart_folders: Vec<SmartFolder>,
+    pub rules: Vec<RuleConfig>,
+    /// Restore the last selection and scroll position on start.
+    pub restore_session: bool,
+    /// Aggregator to pull subscriptions and read state from on refresh.
+    pub sync: Option<SyncConfig>,
+    /// Draw images in the reader; when off only their alt text is shown.
+    pub images: bool,
+    /// Open articles as plain text rather than styled.
+    pub plain_text: bool,
+    pub theme: ThemeConfig,
+    pub layout: LayoutConfig,
+    /// Capture the mouse for scrolling and clicking. Off, the terminal's
+    /// own text selection works.
+    pub mouse: bool,
+    /// Key bindings by context, on top of the defaults; see
+    /// `Keymap::from_config`. Sorted, so they are bound and listed in the
+    /// same order on every run.
+    pub keys: BTreeMap<String, BTreeMap<String, String>>,
 }
 
 impl Default for Config {
     fn default() -> Config {
         let home_path = env::home_dir().unwrap();
         Config {
-            feed_dir: String::from(home_path.join(".russ/feeds").to_str().unwrap()),
+            data_dir: String::from("~/.russ"),
+            feed_dir: String::from("feeds"),
             config_dir: String::from(home_path.join(".config/russ").to_str().unwrap()),
+            storage: Backend::default(),
             feeds: Vec::default(),
+            retention: Retention::default(),
+            smart_folders: Vec::default(),
+            rules: Vec::default(),
+            restore_session: true,
+            sync: None,
+            images: true,
+            plain_text: false,
+            theme: ThemeConfig::default(),
+            layout: LayoutConfig::default(),
+            mouse: true,
+            keys: BTreeMap::new(),
+        }
+    }
+}
+
+/// A subscribed feed: either a bare URL or a table with per-feed options.
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub enum FeedConfig {
+    Url(String),
+    Options(FeedOptions),
+}
+
+#[derive(Deserialize, Clone)]
+pub struct FeedOptions {
+    pub url: String,
+    #[serde(default)]
+    pub retention: Retention,
+    /// Extract the full article from each new post's page on refresh.
+    #[serde(default)]
+    pub full_article: bool,
+}
+
+impl FeedConfig {
+    pub fn url(&self) -> &str {
+        match self {
+            FeedConfig::Url(url) => url,
+            FeedConfig::Options(options) => &options.url,
         }
     }
 }
 
+/// A named query from the config, listed in the main menu after the feeds.
+#[derive(Deserialize)]
+pub struct SmartFolder {
+    pub name: String,
+    pub query: String,
+}
+
+/// What a virtual feed in the main menu lists.
+pub enum Filter {
+    Query(Query),
+    Tag(String),
+    /// Posts from feeds the sync server files under this category.
+    Category(String),
+}
+
+/// Runtime state of a virtual feed (a smart folder or a tag): its filter and
+/// the posts it currently matches, as `(feed, post)` indices into `App::feeds`.
+pub struct Folder {
+    pub name: String,
+    pub filter: Filter,
+    pub posts: Vec<(usize, usize)>,
+}
+
+/// Pending text entry shown on the bottom line.
+pub struct Prompt {
+    pub kind: PromptKind,
+    pub text: String,
+}
+
+pub enum PromptKind {
+    /// Space-separated tags to add to the selected post; `-tag` removes one.
+    Tag,
+    /// A command such as `messages`.
+    Command,
+}
+
+/// Commands that can be typed after `:`.
+pub const COMMANDS: &[&str] = &["messages", "refresh", "help"];
+
+/// A refresh started from the TUI, fetching one feed between frames.
+pub struct Refresh {
+    /// Indexes of the feeds to fetch.
+    pub feeds: Vec<usize>,
+    /// How many of them have been fetched.
+    pub done: usize,
+    pub new_posts: usize,
+    pub failed: usize,
+}
+
+/// The numbered links of the post in the reader, listed in a popup so one
+/// can be opened, copied or subscribed to.
+pub struct LinkPicker {
+    pub links: Vec<String>,
+    pub state: ListState,
+    /// Digits typed so far to jump to a link by number.
+    pub number: String,
+}
+
+impl LinkPicker {
+    pub fn selected(&self) -> Option<&String> {
+        self.links.get(self.state.selected()?)
+    }
+
+    /// Adds a digit to the number being typed and selects that link. Once
+    /// the number runs past the last link it starts over from this digit.
+    pub fn type_digit(&mut self, digit: char) {
+        let valid = |number: &str| {
+            number
+                .parse::<usize>()
+                .ok()
+                .filter(|n| (1..=self.links.len()).contains(n))
+        };
+        self.number.push(digit);
+        if valid(&self.number).is_none() {
+            self.number = digit.to_string();
+        }
+        if let Some(n) = valid(&self.number) {
+            self.state.select(Some(n - 1));
+        }
+    }
+}
+
+/// Entry selected in the main menu.
+#[derive(Clone, Copy)]
+pub enum Selection {
+    Feed(usize),
+    Folder(usize),
+}
+
 pub struct App {
     pub current_screen: Screen,
     pub index: Index,
     pub feeds: Vec<feed::RussFeed>,
-    pub text_width: u16,
+    pub folders: Vec<Folder>,
+    pub view: View,
+    pub prompt: Option<Prompt>,
+    pub picker: Option<LinkPicker>,
+    /// The key help overlay is open.
+    pub help: bool,
+    /// The `:messages` log is open.
+    pub log: bool,
+    pub messages: Messages,
+    pub refreshing: Option<Refresh>,
+    pub keymap: Keymap,
+    pub rules: Vec<Rule>,
+    pub config: Config,
+    pub store: Box<dyn Store>,
+    pub paths: Paths,
+    /// Held for the lifetime of the app; see `store::lock_data_dir`.
+    pub lock: Option<File>,
+    /// Set up once the terminal is, unless images are turned off.
+    pub images: Option<Images>,
+    pub article: Option<ArticleCache>,
+    pub theme: Theme,
+    pub layout: Layout,
 }
 
 impl App {
     pub fn new() -> App {
+        let config = Config::default();
+        let paths = Paths::new(&config.data_dir, &config.feed_dir);
         App {
             current_screen: Screen::MainMenu,
-            index: Index {
-                meta: Vec::new(),
-                state: ListState::default().with_selected(Some(0)),
-            },
+            index: Index::default(),
             feeds: Vec::new(),
-            text_width: 64,
+            folders: Vec::new(),
+            view: View::default(),
+            prompt: None,
+            picker: None,
+            help: false,
+            log: false,
+            messages: Messages::default(),
+            refreshing: None,
+            keymap: Keymap::default(),
+            rules: Vec::new(),
+            config,
+            store: Box::new(JsonStore::new(paths.clone())),
+            paths,
+            lock: None,
+            images: None,
+            article: None,
+            theme: Theme::builtin(ThemeName::from_env()),
+            layout: Layout::new(LayoutConfig::default()),
         }
     }
 
-    pub async fn load_from_config(&mut self) -> Result<(), Box<dyn Error>> {
+    /// Reads the config and sets up everything that doesn't need the store.
+    pub fn load_config(&mut self) -> Result<(), Box<dyn Error>> {
         let mut path = env::home_dir().unwrap();
         path.push(".config/russ/");
         path.push("config.toml");
         let config_str = fs::read_to_string(path)?;
-        let config: Config = toml::from_str(&config_str)?;
+        self.config = toml::from_str(&config_str)?;
 
-        // for url in config.feeds {
-        //     println!("Adding feed {}", url);
-        //     self.add_channel(&url.as_str()).await?;
-        // }
-        _ = self.load_all();
+        self.paths = Paths::new(&self.config.data_dir, &self.config.feed_dir);
+        self.view.plain = self.config.plain_text;
+        self.theme = Theme::from_config(&self.config.theme)?;
+        self.keymap = Keymap::from_config(&self.config.keys)?;
+        self.layout = Layout::new(self.config.layout.clone());
+
+        for rule in &self.config.rules {
+            self.rules.push(Rule::from_config(rule)?);
+        }
+
+        for folder in &self.config.smart_folders {
+            let query = Query::parse(&folder.query)
+                .map_err(|e| format!("Smart folder '{}': {}", folder.name, e))?;
+            self.folders.push(Folder {
+                name: folder.name.clone(),
+                filter: Filter::Query(query),
+                posts: Vec::new(),
+            });
+        }
 
         Ok(())
     }
 
+    /// Locks the data dir and loads the store, for changing it.
+    pub fn open_store(&mut self) -> Result<(), Box<dyn Error>> {
+        self.lock = Some(store::lock_data_dir(&self.paths.data_dir)?);
+        self.store = store::open(self.config.storage, &self.paths)?;
+        self.load_all()
+    }
+
+    /// Loads the store without the lock and without writing to it, for
+    /// commands that only read, even while the TUI is running.
+    pub fn open_store_read_only(&mut self) -> Result<(), Box<dyn Error>> {
+        self.store = store::open_read_only(self.config.storage, &self.paths)?;
+        self.load_all()
+    }
+
     pub fn load_all(&mut self) -> Result<(), Box<dyn Error>> {
-        let mut path = env::home_dir().unwrap();
-        path.push(".russ/feeds/");
-        _ = fs::create_dir(path);
+        (self.index, self.feeds) = self.store.load()?;
+
+        Ok(())
+    }
+
+    /// Re-evaluates every smart folder against the loaded feeds and rebuilds
+    /// the category and tag folders from those currently in use.
+    pub fn update_folders(&mut self) {
+        let mut categories: Vec<&String> = self
+            .feeds
+            .iter()
+            .filter_map(|feed| feed.meta.category.as_ref())
+            .collect();
+        categories.sort();
+        categories.dedup();
+
+        let mut tags: Vec<&String> = self
+            .feeds
+            .iter()
+            .flat_map(|feed| feed.posts.iter().flat_map(|post| &post.tags))
+            .collect();
+        tags.sort();
+        tags.dedup();
+
+        let mut tag_folders = Vec::new();
+        for category in categories {
+            tag_folders.push(Folder {
+                name: format!("{category}/"),
+                filter: Filter::Category(category.clone()),
+                posts: Vec::new(),
+            });
+        }
+        for tag in tags {
+            tag_folders.push(Folder {
+                name: format!("#{tag}"),
+                filter: Filter::Tag(tag.clone()),
+                posts: Vec::new(),
+            });
+        }
+        self.folders
+            .retain(|f| matches!(f.filter, Filter::Query(_)));
+        self.folders.extend(tag_folders);
+
+        for folder in &mut self.folders {
+            folder.posts.clear();
+            for (i, feed) in self.feeds.iter().enumerate() {
+                for (j, post) in feed.posts.iter().enumerate() {
+                    let matches = match &folder.filter {
+                        Filter::Query(query) => query.matches(&feed.meta, post),
+                        Filter::Tag(tag) => post.tags.contains(tag),
+                        Filter::Category(category) => feed.meta.category.as_ref() == Some(category),
+                    };
+                    if !post.hidden && matches {
+                        folder.posts.push((i, j));
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn selection(&self) -> Option<Selection> {
+        self.selection_at(self.view.menu.selected()?)
+    }
+
+    fn selection_at(&self, i: usize) -> Option<Selection> {
+        if i < self.feeds.len() {
+            Some(Selection::Feed(i))
+        } else if i - self.feeds.len() < self.folders.len() {
+            Some(Selection::Folder(i - self.feeds.len()))
+        } else {
+            None
+        }
+    }
+
+    /// Stable name for a main menu entry, used to key its list state.
+    pub fn entry_key(&self, selection: Selection) -> String {
+        match selection {
+            Selection::Feed(i) => self.feeds[i].meta.id.clone(),
+            Selection::Folder(i) => format!("folder:{}", self.folders[i].name),
+        }
+    }
+
+    /// Posts listed under a main menu entry.
+    pub fn entry_posts(&self, selection: Selection) -> Vec<(usize, usize)> {
+        match selection {
+            Selection::Feed(i) => (0..self.feeds[i].posts.len())
+                .filter(|&j| !self.feeds[i].posts[j].hidden)
+                .map(|j| (i, j))
+                .collect(),
+            Selection::Folder(i) => self.folders[i].posts.clone(),
+        }
+    }
+
+    /// Posts listed under the selected main menu entry.
+    pub fn posts(&self) -> Vec<(usize, usize)> {
+        match self.selection() {
+            Some(selection) => self.entry_posts(selection),
+            None => Vec::new(),
+        }
+    }
+
+    /// List state of the post list under the selected main menu entry.
+    pub fn post_state(&mut self) -> Option<&mut ListState> {
+        let key = self.entry_key(self.selection()?);
+        Some(self.view.list(&key))
+    }
+
+    /// `(feed, post)` indices of the selected post.
+    pub fn selected_post(&mut self) -> Option<(usize, usize)> {
+        let posts = self.posts();
+        let selected = self.post_state()?.selected()?;
+        posts.get(selected).copied()
+    }
+
+    /// Marks the selected post as read and writes its feed back to disk.
+    pub fn open_post(&mut self) {
+        if let Some((ch, p)) = self.selected_post() {
+            self.view.scroll = (0, 0);
+            self.view.original = false;
+            if !self.feeds[ch].posts[p].read {
+                self.feeds[ch].posts[p].read = true;
+                self.feeds[ch].posts[p].touch();
+                self.save_post_state(ch, p);
+            }
+            self.current_screen = Screen::Reader;
+        }
+    }
+
+    /// Where keys are pressed now; `None` while asking whether to quit.
+    pub fn key_context(&self) -> Option<Context> {
+        if self.picker.is_some() {
+            return Some(Context::Links);
+        }
+        match self.current_screen {
+            Screen::MainMenu => Some(Context::Main),
+            Screen::FeedMenu => Some(Context::Feed),
+            Screen::Reader => Some(Context::Reader),
+            Screen::Exiting => None,
+        }
+    }
+
+    /// Starts refreshing all feeds from the TUI. Key handling is
+    /// synchronous, so this waits for the sync; the feeds are then fetched
+    /// one at a time by `refresh_step`.
+    pub fn start_refresh(&mut self) {
+        if self.refreshing.is_some() {
+            return;
+        }
+        let runtime = tokio::runtime::Handle::current();
+        match tokio::task::block_in_place(|| runtime.block_on(self.refresh_start())) {
+            Ok(new_posts) => {
+                self.refreshing = Some(Refresh {
+                    feeds: self.feeds_to_fetch(),
+                    done: 0,
+                    new_posts,
+                    failed: 0,
+                });
+                self.update_folders();
+            }
+            Err(e) => self.messages.error(format!("Refresh failed: {e}")),
+        }
+    }
+
+    /// Fetches the next feed of the refresh in progress, and finishes the
+    /// refresh after the last one.
+    pub fn refresh_step(&mut self) {
+        let Some(refresh) = &self.refreshing else {
+            return;
+        };
+        if let Some(&i) = refresh.feeds.get(refresh.done) {
+            let runtime = tokio::runtime::Handle::current();
+            let result = tokio::task::block_in_place(|| runtime.block_on(self.refresh_feed(i)));
+            let feed = &self.feeds[i];
+            let Some(refresh) = &mut self.refreshing else {
+                return;
+            };
+            refresh.done += 1;
+            match &result {
+                Ok(count) => refresh.new_posts += count,
+                Err(e) => self
+                    .messages
+                    .error(format!("Could not save {}: {e}", feed.meta.title)),
+            }
+            if let Some(error) = &feed.meta.fetch_error {
+                self.messages
+                    .error(format!("Could not fetch {}: {error}", feed.meta.title));
+            }
+            // A feed that failed to fetch and then to save counts once.
+            if result.is_err() || feed.meta.fetch_error.is_some() {
+                refresh.failed += 1;
+            }
+            self.update_folders();
+            return;
+        }
+
+        let Some(refresh) = self.refreshing.take() else {
+            return;
+        };
+        if let Err(e) = self.refresh_finish() {
+            self.messages.error(format!("Refresh failed: {e}"));
+            return;
+        }
+        let mut text = format!(
+            "Refreshed {} feeds: {} new posts",
+            refresh.feeds.len(),
+            refresh.new_posts
+        );
+        if refresh.failed > 0 {
+            text += &format!(", {} failed", refresh.failed);
+        }
+        self.messages.info(text);
+    }
+
+    /// Writes post `p` of feed `ch` to the store, reporting a failure in
+    /// the status bar.
+    fn save_post_state(&mut self, ch: usize, p: usize) {
+        let (feed, post) = (&self.feeds[ch], &self.feeds[ch].posts[p]);
+        if let Err(e) = self.store.save_post_state(feed, post) {
+            self.messages
+                .error(format!("Could not save {}: {e}", post.title));
+        }
+    }
+
+    /// Marks the selected post read or unread.
+    pub fn set_read(&mut self, read: bool) {
+        if let Some((ch, p)) = self.selected_post() {
+            let post = &mut self.feeds[ch].posts[p];
+            if post.read == read {
+                return;
+            }
+            post.read = read;
+            post.touch();
+            self.save_post_state(ch, p);
+            self.update_folders();
+        }
+    }
+
+    /// Opens the post `offset` places from the selected one in its list,
+    /// going no further than either end.
+    pub fn open_adjacent(&mut self, offset: isize) {
+        let last = self.posts().len().saturating_sub(1);
+        let Some(state) = self.post_state() else {
+            return;
+        };
+        let Some(selected) = state.selected() else {
+            return;
+        };
+        let target = selected.saturating_add_signed(offset).min(last);
+        if target != selected {
+            state.select(Some(target));
+            self.open_post();
+        }
+    }
+
+    /// Selects the next unread post, like `n` in mutt: further down the
+    /// current list, then in the feeds after it, wrapping around to the
+    /// first. The reader opens it; elsewhere it is selected in its feed.
+    /// Returns false when there is nothing unread.
+    pub fn next_unread(&mut self) -> bool {
+        let Some(current) = self.view.menu.selected() else {
+            return false;
+        };
+        let after = match self.current_screen {
+            Screen::MainMenu => None,
+            _ => self.post_state().and_then(|state| state.selected()),
+        };
+
+        let feeds = self.feeds.len();
+        let mut order = vec![(current, after.map_or(0, |i| i + 1))];
+        order.extend(
+            (current + 1..feeds)
+                .chain(0..current.min(feeds))
+                .map(|i| (i, 0)),
+        );
+        order.push((current, 0));
+
+        for (entry, start) in order {
+            let Some(selection) = self.selection_at(entry) else {
+                continue;
+            };
+            let found = self
+                .entry_posts(selection)
+                .iter()
+                .enumerate()
+                .skip(start)
+                .find(|(i, (ch, p))| {
+                    let selected = entry == current && Some(*i) == after;
+                    !selected && !self.feeds[*ch].posts[*p].read
+                })
+                .map(|(i, _)| i);
+            if let Some(i) = found {
+                self.view.menu.select(Some(entry));
+                let key = self.entry_key(selection);
+                self.view.list(&key).select(Some(i));
+                match self.current_screen {
+                    Screen::Reader => self.open_post(),
+                    _ => self.current_screen = Screen::FeedMenu,
+                }
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn toggle_star(&mut self) {
+        if let Some((ch, p)) = self.selected_post() {
+            let post = &mut self.feeds[ch].posts[p];
+            post.starred = !post.starred;
+            post.touch();
+            self.save_post_state(ch, p);
+            self.update_folders();
+        }
+    }
+
+    /// Merges another machine's copy of the store into this one, feed by
+    /// feed. See `RussFeed::merge_replica`.
+    pub fn merge_replica(
+        &mut self,
+        other_feeds: Vec<feed::RussFeed>,
+    ) -> Result<(), Box<dyn Error>> {
+        for other_feed in other_feeds {
+            match self
+                .feeds
+                .iter_mut()
+                .find(|f| f.meta.same_feed(&other_feed.meta))
+            {
+                Some(feed) => {
+                    feed.merge_replica(other_feed);
+                    self.store.save_feed(feed)?;
+                }
+                None => {
+                    self.index.meta.push(other_feed.meta.clone());
+                    self.store.save_feed(&other_feed)?;
+                    self.feeds.push(other_feed);
+                }
+            }
+        }
+        self.store.save_index(&self.index)?;
+        self.update_folders();
+        Ok(())
+    }
+
+    /// Snapshot of the current selections for `session.json`.
+    pub fn session(&self) -> Session {
+        let mut session = Session {
+            version: session::SESSION_VERSION,
+            entry: self.selection().map(|s| self.entry_key(s)),
+            reading: matches!(self.current_screen, Screen::Reader),
+            scroll: self.view.scroll,
+            ..Default::default()
+        };
 
-        self.index = Index::from_file()?;
+        for i in 0..self.feeds.len() + self.folders.len() {
+            let Some(selection) = self.selection_at(i) else {
+                continue;
+            };
+            let key = self.entry_key(selection);
+            let selected = self.view.lists.get(&key).and_then(|s| s.selected());
+            if let Some((ch, p)) =
+                selected.and_then(|j| self.entry_posts(selection).get(j).copied())
+            {
+                session
+                    .posts
+                    .insert(key, self.feeds[ch].posts[p].key().to_string());
+            }
+        }
+
+        session
+    }
+
+    /// Restores the selections saved by `session`, skipping any feed or
+    /// post that no longer exists.
+    pub fn restore(&mut self, session: Session) {
+        for i in 0..self.feeds.len() + self.folders.len() {
+            let Some(selection) = self.selection_at(i) else {
+                continue;
+            };
+            let key = self.entry_key(selection);
+            if session.entry.as_ref() == Some(&key) {
+                self.view.menu.select(Some(i));
+            }
+            let Some(post_key) = session.posts.get(&key) else {
+                continue;
+            };
+            let position = self
+                .entry_posts(selection)
+                .iter()
+                .position(|&(ch, p)| self.feeds[ch].posts[p].key() == post_key);
+            if let Some(j) = position {
+                self.view.list(&key).select(Some(j));
+            }
+        }
+
+        if session.reading && self.selected_post().is_some() {
+            self.current_screen = Screen::Reader;
+            self.view.scroll = session.scroll;
+        }
+    }
+
+    fn feed_urls(&self) -> Vec<String> {
+        self.config
+            .feeds
+            .iter()
+            .map(|f| f.url().to_string())
+            .collect()
+    }
+
+    /// Retention policy for the feed fetched from `source`: the global policy
+    /// with the feed's own overrides applied.
+    pub fn retention_for(&self, source: &str) -> Retention {
+        match self.config.feeds.iter().find(|f| f.url() == source) {
+            Some(FeedConfig::Options(options)) => {
+                self.config.retention.with_override(&options.retention)
+            }
+            _ => self.config.retention.clone(),
+        }
+    }
+
+    /// Whether new posts from the feed fetched from `source` get their full
+    /// article extracted on refresh.
+    pub fn full_article_for(&self, source: &str) -> bool {
+        matches!(
+            self.config.feeds.iter().find(|f| f.url() == source),
+            Some(FeedConfig::Options(options)) if options.full_article
+        )
+    }
 
-        self.index.meta.iter().for_each(|meta| {
-            self.feeds
-                .push(feed::RussFeed::from_file(meta.id.clone()).unwrap());
+    /// Switches the reader between the feed's content and the extracted
+    /// article, fetching and extracting the article first if needed.
+    pub fn toggle_extracted(&mut self) -> Result<(), Box<dyn Error>> {
+        let Some((ch, p)) = self.selected_post() else {
+            return Ok(());
+        };
+        if self.feeds[ch].posts[p].extracted.is_some() {
+            self.view.original = !self.view.original;
+            return Ok(());
+        }
+
+        // Key handling is synchronous, so wait for the download here.
+        let link = self.feeds[ch].posts[p].link.clone();
+        let runtime = tokio::runtime::Handle::current();
+        let article = tokio::task::block_in_place(|| runtime.block_on(fetch_article(&link)))?
+            .ok_or("No article found on the page")?;
+        self.feeds[ch].posts[p].extracted = Some(article);
+        self.view.original = false;
+        self.store.save_feed(&self.feeds[ch])?;
+        Ok(())
+    }
+
+    pub fn start_prompt(&mut self, kind: PromptKind) {
+        self.prompt = Some(Prompt {
+            kind,
+            text: String::new(),
         });
+    }
+
+    pub fn submit_prompt(&mut self) {
+        let Some(prompt) = self.prompt.take() else {
+            return;
+        };
+        match prompt.kind {
+            PromptKind::Tag => self.edit_tags(&prompt.text),
+            PromptKind::Command => self.run_command(&prompt.text),
+        }
+    }
 
+    /// Runs a command typed after `:`. Any unambiguous prefix of a command
+    /// works, as in Vim.
+    pub fn run_command(&mut self, input: &str) {
+        let input = input.trim();
+        if input.is_empty() {
+            return;
+        }
+        match COMMANDS.iter().find(|command| command.starts_with(input)) {
+            Some(&"messages") => self.log = true,
+            Some(&"refresh") => self.start_refresh(),
+            Some(&"help") => self.help = true,
+            _ => self.messages.error(format!("Unknown command: {input}")),
+        }
+    }
+
+    /// Adds each word in `input` as a tag on the selected post, or removes it
+    /// when prefixed with `-`.
+    pub fn edit_tags(&mut self, input: &str) {
+        let Some((ch, p)) = self.selected_post() else {
+            return;
+        };
+        let post = &mut self.feeds[ch].posts[p];
+        for word in input.split_whitespace() {
+            match word.strip_prefix('-') {
+                Some(tag) => post.tags.retain(|t| t != tag),
+                None => {
+                    if !post.tags.iter().any(|t| t == word) {
+                        post.tags.push(word.to_string());
+                    }
+                }
+            }
+        }
+        post.touch();
+        self.save_post_state(ch, p);
+        self.update_folders();
+    }
+
+    /// Opens the link picker on the post in the reader, if it has links.
+    pub fn open_link_picker(&mut self) {
+        // The links as the reader numbered them.
+        let Some(cache) = &self.article else {
+            return;
+        };
+        let links = cache.article.targets.clone();
+        if links.is_empty() {
+            return;
+        }
+        self.picker = Some(LinkPicker {
+            links,
+            state: ListState::default().with_selected(Some(0)),
+            number: String::new(),
+        });
+    }
+
+    /// Opens the picked link in the browser.
+    pub fn open_link(&mut self) {
+        let Some(picker) = &mut self.picker else {
+            return;
+        };
+        let Some(url) = picker.selected() else {
+            return;
+        };
+        match open_in_browser(url) {
+            Ok(()) => self.messages.info(format!("Opened {url}")),
+            Err(e) => self.messages.error(format!("Could not open {url}: {e}")),
+        }
+    }
+
+    /// Opens the link drawn at `column` of `line` of the article in the
+    /// reader, if there is one.
+    pub fn open_article_link(&mut self, line: usize, column: usize) {
+        if let Some(url) = self
+            .article
+            .as_ref()
+            .and_then(|c| c.article.link_at(line, column))
+        {
+            match open_in_browser(url) {
+                Ok(()) => self.messages.info(format!("Opened {url}")),
+                Err(e) => self.messages.error(format!("Could not open {url}: {e}")),
+            }
+        }
+    }
+
+    /// Copies the picked link to the terminal's clipboard.
+    pub fn copy_link(&mut self) {
+        let Some(picker) = &mut self.picker else {
+            return;
+        };
+        let Some(url) = picker.selected() else {
+            return;
+        };
+        match copy_to_clipboard(url) {
+            Ok(()) => self.messages.info(format!("Copied {url}")),
+            Err(e) => self.messages.error(format!("Could not copy {url}: {e}")),
+        }
+    }
+
+    /// Subscribes to the picked link as a feed.
+    pub fn subscribe_link(&mut self) {
+        let Some(url) = self.picker.as_ref().and_then(|p| p.selected()).cloned() else {
+            return;
+        };
+
+        // Key handling is synchronous, so wait for the fetch here.
+        let runtime = tokio::runtime::Handle::current();
+        let result = tokio::task::block_in_place(|| runtime.block_on(self.add_channel(&url)));
+        match result {
+            Ok(()) => {
+                self.update_folders();
+                self.messages.info(format!("Subscribed to {url}"));
+            }
+            Err(e) => self
+                .messages
+                .error(format!("Could not subscribe to {url}: {e}")),
+        }
+    }
+
+    /// Subscribes to the feeds in the config that aren't in the index yet.
+    /// A feed that can't be fetched is still added, with the error kept in
+    /// its `fetch_error`, so one bad URL doesn't stop russ from starting.
+    pub async fn add_config_feeds(&mut self) -> Result<(), Box<dyn Error>> {
+        for url in self.feed_urls() {
+            if self.index.meta.iter().any(|meta| meta.source == url) {
+                continue;
+            }
+            if let Err(e) = self.add_channel(&url).await {
+                let feed = feed::RussFeed {
+                    meta: feed::Metadata {
+                        id: feed::stable_id(&url),
+                        title: url.clone(),
+                        url: url.clone(),
+                        source: url.clone(),
+                        fetched_at: Some(Utc::now()),
+                        fetch_error: Some(e.to_string()),
+                        ..Default::default()
+                    },
+                    posts: Vec::new(),
+                    pruned: Vec::new(),
+                };
+                self.index.meta.push(feed.meta.clone());
+                self.store.save_feed(&feed)?;
+                self.feeds.push(feed);
+                self.store.save_index(&self.index)?;
+            }
+        }
         Ok(())
     }
 
     pub async fn add_channel(&mut self, url: &str) -> Result<(), Box<dyn Error>> {
+        let mut feed = fetch_feed(url).await?;
+        feed.meta.fetched_at = Some(Utc::now());
 
-        let mut path = env::home_dir().unwrap();
-        path.push(".russ/feeds/");
-
-        if !path.exists() {
-            fs::create_dir(path).expect("Failed to create feed dir");
-        }
-
-        let content = reqwest::get(url).await?.bytes().await?;
-        let xml = content.as_ref();
-
-        // Check content type
-        let mut feed_type: Option<FeedType> = None;
-        let mut reader = quick_xml::Reader::from_str(std::str::from_utf8(xml)?);
-        reader.config_mut().trim_text(true);
-        let mut buf = Vec::new();
-        loop {
-            match reader.read_event_into(&mut buf) {
-                Err(e) => panic!("Error at position {}: {:?}", reader.error_position(), e),
-                Ok(events::Event::Eof) => break,
-                Ok(events::Event::Start(e)) => match e.name().as_ref() {
-                    b"rss" => feed_type = Some(FeedType::RSS),
-                    b"feed" => feed_type = Some(FeedType::Atom),
-                    _ => (),
+        // Older indexes have no source and may use a different id scheme, so
+        // also recognise a known feed by its link.
+        let known = self
+            .index
+            .meta
+            .iter()
+            .position(|meta| meta.id == feed.meta.id || meta.url == feed.meta.url);
+        match known {
+            Some(i) => {
+                self.index.meta[i].source = feed.meta.source.clone();
+                self.feeds[i].meta.source = feed.meta.source;
+                self.store.save_feed(&self.feeds[i])?;
+            }
+            None => {
+                rules::apply_rules(&self.rules, &feed.meta, feed.posts.iter_mut());
+                self.index.meta.push(feed.meta.clone());
+                self.store.save_feed(&feed)?;
+                self.feeds.push(feed);
+            }
+        }
+        self.store.save_index(&self.index)?;
+        Ok(())
+    }
+
+    /// Fetches every subscribed feed again and merges in new posts, running
+    /// the configured rules on them. A feed that fails to fetch keeps its
+    /// posts and records the error. Returns the number of new posts.
+    pub async fn refresh(&mut self) -> Result<usize, Box<dyn Error>> {
+        let mut new_posts = self.refresh_start().await?;
+        for i in self.feeds_to_fetch() {
+            new_posts += self.refresh_feed(i).await?;
+        }
+        self.refresh_finish()?;
+        Ok(new_posts)
+    }
+
+    /// The first step of a refresh: syncs with the server and adds feeds
+    /// new to the config. Returns the number of new posts from the server.
+    pub async fn refresh_start(&mut self) -> Result<usize, Box<dyn Error>> {
+        let mut new_posts = 0;
+
+        if let Some(config) = self.config.sync.clone() {
+            new_posts += self
+                .sync(&config)
+                .await
+                .map_err(|e| format!("Sync with {} failed: {e}", config.url))?;
+        }
+
+        self.add_config_feeds().await?;
+        Ok(new_posts)
+    }
+
+    /// Indexes of the feeds a refresh fetches directly.
+    pub fn feeds_to_fetch(&self) -> Vec<usize> {
+        (0..self.feeds.len())
+            .filter(|&i| {
+                !self.feeds[i].meta.source.is_empty() && self.feeds[i].meta.remote.is_none()
+            })
+            .collect()
+    }
+
+    /// Fetches feed `i` and merges its new posts, returning how many there
+    /// were. A failed fetch is kept in the feed's `fetch_error`.
+    pub async fn refresh_feed(&mut self, i: usize) -> Result<usize, Box<dyn Error>> {
+        let source = self.feeds[i].meta.source.clone();
+        let policy = self.retention_for(&source);
+        let full_article = self.full_article_for(&source);
+        let feed = &mut self.feeds[i];
+        let mut new_posts = 0;
+        feed.meta.fetched_at = Some(Utc::now());
+        match fetch_feed(&source).await {
+            Ok(fetched) => {
+                feed.meta.fetch_error = None;
+                let count = feed.merge(fetched);
+                rules::apply_rules(&self.rules, &feed.meta, feed.posts.iter_mut().take(count));
+                if full_article {
+                    extract_articles(feed.posts.iter_mut().take(count)).await;
+                }
+                retention::prune(feed, &policy, false);
+                new_posts += count;
+            }
+            Err(e) => feed.meta.fetch_error = Some(e.to_string()),
+        }
+        self.store.save_feed(feed)?;
+        Ok(new_posts)
+    }
+
+    /// The last step of a refresh: saves the index and rebuilds the folders.
+    pub fn refresh_finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.index.meta = self.feeds.iter().map(|f| f.meta.clone()).collect();
+        self.store.save_index(&self.index)?;
+        self.update_folders();
+        Ok(())
+    }
+
+    /// Sends read/star changes made since the last sync to the server, then
+    /// mirrors its subscriptions, new items and unread/starred state. Feeds
+    /// unsubscribed on the server are dropped. Returns the number of new
+    /// posts.
+    async fn sync(&mut self, config: &SyncConfig) -> Result<usize, Box<dyn Error>> {
+        let started = Utc::now();
+        let client = sync::Client::connect(config).await?;
+
+        // A feed's fetched_at is when it was last synced.
+        let changed: Vec<&feed::Post> = self
+            .feeds
+            .iter()
+            .filter(|f| f.meta.remote.is_some())
+            .flat_map(|f| {
+                f.posts
+                    .iter()
+                    .filter(|p| p.state_changed > f.meta.fetched_at)
+            })
+            .collect();
+        client.push(&changed).await?;
+
+        // The server has these stars now, even if the pull below fails.
+        for feed in self.feeds.iter_mut().filter(|f| f.meta.remote.is_some()) {
+            let synced = feed.meta.fetched_at;
+            for p in 0..feed.posts.len() {
+                if feed.posts[p].state_changed > synced {
+                    feed.posts[p].server_starred = Some(feed.posts[p].starred);
+                    self.store.save_post_state(feed, &feed.posts[p])?;
+                }
+            }
+        }
+
+        let known: HashSet<String> = self
+            .feeds
+            .iter()
+            .filter(|f| f.meta.remote.is_some())
+            .flat_map(|f| f.posts.iter().map(|p| p.id.clone()).chain(f.pruned.clone()))
+            .collect();
+        let mut snapshot = client.pull(&known).await?;
+
+        self.feeds.retain(|f| {
+            f.meta
+                .remote
+                .as_ref()
+                .is_none_or(|id| snapshot.subscriptions.iter().any(|s| &s.id == id))
+        });
+
+        let mut new_posts = 0;
+        for subscription in snapshot.subscriptions {
+            let i = match self
+                .feeds
+                .iter()
+                .position(|f| f.meta.remote.as_ref() == Some(&subscription.id))
+            {
+                Some(i) => i,
+                None => {
+                    self.feeds.push(feed::RussFeed {
+                        meta: feed::Metadata {
+                            id: feed::stable_id(&subscription.url),
+                            remote: Some(subscription.id.clone()),
+                            ..Default::default()
+                        },
+                        posts: Vec::new(),
+                        pruned: Vec::new(),
+                    });
+                    self.feeds.len() - 1
+                }
+            };
+
+            let policy = self.retention_for(&subscription.url);
+            let full_article = self.full_article_for(&subscription.url);
+            let feed = &mut self.feeds[i];
+            feed.meta.url = subscription.site_url;
+            feed.meta.source = subscription.url;
+            feed.meta.category = subscription.category;
+            feed.meta.fetch_error = None;
+
+            let (items, rest) = snapshot
+                .items
+                .into_iter()
+                .partition(|(id, _)| *id == subscription.id);
+            snapshot.items = rest;
+            let fetched = feed::RussFeed {
+                meta: feed::Metadata {
+                    title: subscription.title,
+                    ..Default::default()
                 },
-                _ => (),
+                posts: items.into_iter().map(|(_, post)| post).collect::<Vec<_>>(),
+                pruned: Vec::new(),
+            };
+            let count = feed.merge(fetched);
+
+            for post in &mut feed.posts {
+                post.read = !snapshot.unread.contains(&post.id);
+                post.starred = snapshot.starred.contains(&post.id);
+                post.server_starred = Some(post.starred);
+            }
+            rules::apply_rules(&self.rules, &feed.meta, feed.posts.iter_mut().take(count));
+            if full_article {
+                extract_articles(feed.posts.iter_mut().take(count)).await;
+            }
+            retention::prune(feed, &policy, false);
+
+            feed.meta.fetched_at = Some(started);
+            self.store.save_feed(feed)?;
+            new_posts += count;
+        }
+
+        Ok(new_posts)
+    }
+}
+
+/// Client for article pages. Extraction runs while the TUI waits, so a
+/// page that doesn't answer is given up on.
+static ARTICLE_CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .unwrap_or_default()
+});
+
+/// Pages fetched at once when extracting articles on refresh.
+const CONCURRENT_EXTRACTIONS: usize = 4;
+
+/// Downloads the page at `url` and extracts its article, if it has one. The
+/// page is decoded with the charset its Content-Type names.
+pub async fn fetch_article(url: &str) -> Result<Option<String>, Box<dyn Error>> {
+    let html = ARTICLE_CLIENT
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    Ok(readability::extract(html.as_bytes()))
+}
+
+/// Extracts the full article of each visible post that has a link, a few
+/// pages at a time. A page that fails to load leaves the post with the
+/// feed's content.
+async fn extract_articles(posts: impl Iterator<Item = &mut feed::Post>) {
+    let mut posts: Vec<_> = posts.filter(|p| !p.hidden && !p.link.is_empty()).collect();
+    let links: Vec<String> = posts.iter().map(|p| p.link.clone()).collect();
+    let mut pending = links.into_iter().enumerate();
+    let mut tasks = JoinSet::new();
+    loop {
+        while tasks.len() < CONCURRENT_EXTRACTIONS
+            && let Some((i, link)) = pending.next()
+        {
+            tasks.spawn(async move { (i, fetch_article(&link).await.ok().flatten()) });
+        }
+        let Some(result) = tasks.join_next().await else {
+            break;
+        };
+        if let Ok((i, Some(article))) = result {
+            posts[i].extracted = Some(article);
+        }
+    }
+}
+
+/// Opens `url` with `$BROWSER`, or the system's default handler.
+fn open_in_browser(url: &str) -> io::Result<()> {
+    let mut command = match env::var("BROWSER") {
+        Ok(browser) if !browser.is_empty() => Command::new(browser),
+        _ if cfg!(target_os = "macos") => Command::new("open"),
+        _ if cfg!(windows) => {
+            let mut command = Command::new("cmd");
+            command.args(["/C", "start", ""]);
+            command
+        }
+        _ => Command::new("xdg-open"),
+    };
+    // The browser must not draw over the TUI.
+    command
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    Ok(())
+}
+
+/// Puts `text` on the clipboard with an OSC 52 escape, which works over SSH
+/// wherever the terminal supports it.
+fn copy_to_clipboard(text: &str) -> io::Result<()> {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(text);
+    let mut stderr = io::stderr();
+    write!(stderr, "\x1b]52;c;{encoded}\x07")?;
+    stderr.flush()
+}
+
+/// Downloads and parses the RSS or Atom feed at `url`.
+pub async fn fetch_feed(url: &str) -> Result<feed::RussFeed, Box<dyn Error>> {
+    let content = reqwest::get(url).await?.bytes().await?;
+    let xml = content.as_ref();
+
+    // Check content type
+    let mut feed_type: Option<FeedType> = None;
+    let mut reader = quick_xml::Reader::from_str(std::str::from_utf8(xml)?);
+    reader.config_mut().trim_text(true);
+    let mut buf = Vec::new();
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Err(e) => {
+                return Err(
+                    format!("Error at position {}: {:?}", reader.error_position(), e).into(),
+                );
             }
+            Ok(events::Event::Eof) => break,
+            Ok(events::Event::Start(e)) => match e.name().as_ref() {
+                b"rss" => feed_type = Some(FeedType::Rss),
+                b"feed" => feed_type = Some(FeedType::Atom),
+                _ => (),
+            },
+            _ => (),
         }
+    }
+
+    let mut feed: feed::RussFeed = match feed_type {
+        Some(FeedType::Rss) => feed::RussFeed::from_rss(Channel::read_from(xml)?)?,
+        Some(FeedType::Atom) => feed::RussFeed::from_atom(Feed::read_from(xml)?)?,
+        None => return Err("Invalid feed syntax".into()),
+    };
+    feed.meta.source = url.to_string();
+
+    Ok(feed)
+}
 
-        let feed: feed::RussFeed = match feed_type {
-            Some(FeedType::RSS) => feed::RussFeed::from_rss(Channel::read_from(&xml[..])?)?,
-            Some(FeedType::Atom) => feed::RussFeed::from_atom(Feed::read_from(&xml[..])?)?,
-            None => return Err("Invalid feed syntax".into()),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::{
+        Api,
+        mock::{MockServer, Request},
+    };
+    use std::sync::{Arc, Mutex};
+
+    /// A Fever server with one unread item that remembers items marked
+    /// read.
+    async fn fever_server() -> MockServer {
+        let unread = Arc::new(Mutex::new(true));
+        MockServer::start(move |request: &Request| {
+            let mut unread = unread.lock().unwrap();
+            if request.param("mark") == Some("item") && request.param("as") == Some("read") {
+                *unread = false;
+            }
+            let items = match request.param("max_id") {
+                Some(_) => r#"{"id": 1, "feed_id": 7, "title": "Football results"}"#,
+                None => "",
+            };
+            let body = format!(
+                r#"{{"auth": 1, "feeds": [{{"id": 7, "title": "Sport", "url": "https://sport/rss"}}],
+                    "items": [{items}], "unread_item_ids": "{}", "saved_item_ids": ""}}"#,
+                if *unread { "1" } else { "" }
+            );
+            (200, body)
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn rule_changes_to_synced_posts_survive_the_next_pull() {
+        let server = fever_server().await;
+        let dir = env::temp_dir().join(format!("russ-sync-{}", std::process::id()));
+        let paths = Paths {
+            data_dir: dir.clone(),
+            feed_dir: dir.join("feeds"),
         };
+        fs::create_dir_all(&paths.feed_dir).unwrap();
 
-        self.index.meta.push(feed.meta.clone());
-        _ = feed.save();
-        _ = self.index.save();
-        Ok(())
+        let mut app = App::new();
+        app.store = Box::new(JsonStore::new(paths.clone()));
+        app.paths = paths;
+        app.rules.push(
+            Rule::from_config(&RuleConfig {
+                name: "no football".to_string(),
+                feed: None,
+                title: Some("football".to_string()),
+                author: None,
+                category: None,
+                content: None,
+                actions: vec!["read".to_string()],
+            })
+            .unwrap(),
+        );
+        let config = SyncConfig {
+            api: Api::Fever,
+            url: server.url.clone(),
+            username: "me".to_string(),
+            password: "secret".to_string(),
+            token: None,
+        };
+
+        assert_eq!(app.sync(&config).await.unwrap(), 1);
+        assert!(app.feeds[0].posts[0].read);
+        app.sync(&config).await.unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(app.feeds[0].posts[0].read);
+        assert!(
+            server
+                .requests()
+                .iter()
+                .any(|r| r.param("as") == Some("read") && r.param("id") == Some("1"))
+        );
     }
 }
@@ -0,0 +1,198 @@
+use chrono::{DateTime, FixedOffset};
+use serde::Serialize;
+use std::{error::Error, fs};
+
+use crate::{
+    app::App,
+    retention, rules,
+    store::{self, Paths, Store, json::JsonStore, sqlite::SqliteStore},
+};
+
+/// Runs a non-interactive subcommand such as `russ refresh`.
+pub async fn run(app: &mut App, command: &str, args: &[String]) -> Result<(), Box<dyn Error>> {
+    match command {
+        "refresh" => {
+            app.open_store()?;
+            let count = app.refresh().await?;
+            println!("{count} new posts");
+            for feed in &app.feeds {
+                if let Some(e) = &feed.meta.fetch_error {
+                    println!("{}: {}", feed.meta.title, e);
+                }
+            }
+        }
+        "import-json" => {
+            app.open_store()?;
+            let mut legacy = JsonStore::new(app.paths.clone());
+            let count = store::import(&mut legacy, app.store.as_mut())?;
+            println!("Imported {count} feeds");
+        }
+        "merge" => {
+            let dir = args.first().ok_or("Usage: russ merge DIR")?;
+            app.open_store()?;
+            // The other machine's directory is only read, never migrated or
+            // tidied.
+            let paths = Paths::new(dir, &app.config.feed_dir);
+            let (_, feeds) = if paths.database().exists() {
+                SqliteStore::open_read_only(&paths.database())?.load()?
+            } else {
+                JsonStore::read_only(paths).load()?
+            };
+            let count = feeds.len();
+            app.merge_replica(feeds)?;
+            println!("Merged {count} feeds from {dir}");
+        }
+        "rules" => rules_command(app, args)?,
+        "export" => {
+            app.open_store_read_only()?;
+            export(app, args.first())?;
+        }
+        "prune" => {
+            let dry_run = args.first().is_some_and(|a| a == "--dry-run");
+            if dry_run {
+                app.open_store_read_only()?;
+            } else {
+                app.open_store()?;
+            }
+            prune(app, dry_run)?;
+        }
+        _ => return Err(format!("Unknown command: {command}").into()),
+    }
+
+    Ok(())
+}
+
+/// `russ rules [--dry-run | --apply] [NAME]`
+///
+/// Without a flag, lists the configured rules. `--dry-run` prints the stored
+/// posts each rule would hit, and `--apply` runs the rules on them.
+fn rules_command(app: &mut App, args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mode = args.first().map(String::as_str);
+    let name = args.get(1);
+    match mode {
+        Some("--dry-run") => app.open_store_read_only()?,
+        Some("--apply") => app.open_store()?,
+        _ => {}
+    }
+    let selected: Vec<&rules::Rule> = app
+        .rules
+        .iter()
+        .filter(|r| name.is_none_or(|n| &r.name == n))
+        .collect();
+
+    if let Some(n) = name
+        && selected.is_empty()
+    {
+        return Err(format!("No rule named '{n}'").into());
+    }
+
+    match mode {
+        None => {
+            for rule in &app.rules {
+                println!("{}", rule.name);
+            }
+        }
+        Some("--dry-run") => {
+            for rule in selected {
+                let mut hits = 0;
+                for feed in &app.feeds {
+                    for post in feed.posts.iter().filter(|p| rule.matches(&feed.meta, p)) {
+                        println!("[{}] {}: {}", rule.name, feed.meta.title, post.title);
+                        hits += 1;
+                    }
+                }
+                println!("[{}] {} posts would match", rule.name, hits);
+            }
+        }
+        Some("--apply") => {
+            for feed in &mut app.feeds {
+                let mut hits = 0;
+                for post in &mut feed.posts {
+                    for rule in &selected {
+                        if rule.matches(&feed.meta, post) {
+                            rule.apply(post);
+                            hits += 1;
+                        }
+                    }
+                }
+                if hits > 0 {
+                    app.store.save_feed(feed)?;
+                    println!("{}: {} rule hits", feed.meta.title, hits);
+                }
+            }
+        }
+        Some(flag) => return Err(format!("Unknown flag: {flag}").into()),
+    }
+
+    Ok(())
+}
+
+/// `russ prune [--dry-run]`
+///
+/// Applies the retention policy to every feed and reports what was (or,
+/// with `--dry-run`, would be) removed.
+fn prune(app: &mut App, dry_run: bool) -> Result<(), Box<dyn Error>> {
+    let (mut posts, mut bytes) = (0, 0);
+    for i in 0..app.feeds.len() {
+        let policy = app.retention_for(&app.feeds[i].meta.source);
+        let feed = &mut app.feeds[i];
+        let pruned = retention::prune(feed, &policy, dry_run);
+        if pruned.posts == 0 {
+            continue;
+        }
+        if !dry_run {
+            app.store.save_feed(feed)?;
+        }
+        println!(
+            "{}: {} posts, {} bytes",
+            feed.meta.title, pruned.posts, pruned.bytes
+        );
+        posts += pruned.posts;
+        bytes += pruned.bytes;
+    }
+
+    let verb = if dry_run { "Would remove" } else { "Removed" };
+    println!("{verb} {posts} posts ({bytes} bytes)");
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct ExportedPost<'a> {
+    feed: &'a str,
+    title: &'a str,
+    link: &'a str,
+    published: Option<DateTime<FixedOffset>>,
+    read: bool,
+    starred: bool,
+    tags: &'a [String],
+}
+
+/// `russ export [FILE]`
+///
+/// Writes every stored post's metadata, flags and tags as JSON to FILE, or
+/// to stdout.
+fn export(app: &App, file: Option<&String>) -> Result<(), Box<dyn Error>> {
+    let posts: Vec<ExportedPost> = app
+        .feeds
+        .iter()
+        .flat_map(|feed| {
+            feed.posts.iter().map(|post| ExportedPost {
+                feed: &feed.meta.title,
+                title: &post.title,
+                link: &post.link,
+                published: post.published,
+                read: post.read,
+                starred: post.starred,
+                tags: &post.tags,
+            })
+        })
+        .collect();
+    let serialized = serde_json::to_string_pretty(&posts)?;
+
+    match file {
+        Some(path) => fs::write(path, serialized)?,
+        None => println!("{serialized}"),
+    }
+
+    Ok(())
+}
@@ -1,31 +1,135 @@
-use atom_syndication::{Entry, Feed};
+use atom_syndication::{Entry, Feed, TextType};
+use chrono::{DateTime, FixedOffset, Utc};
+use regex::Regex;
 use rss::{Channel, Item};
 use serde::{Deserialize, Serialize};
-use serde_json;
-use std::{
-    env,
-    error::Error,
-    fs::{self, File},
-    hash::{DefaultHasher, Hash, Hasher},
-    io::Write,
-};
-
-// TODO remove
-use html2text::from_read;
-use ratatui::{crossterm::terminal, text::Text, widgets::ListState};
-
-#[derive(Serialize, Deserialize, Clone)]
+use std::{collections::HashSet, error::Error, fs, path::Path, sync::LazyLock};
+
+use crate::store::{self, json};
+
+#[derive(Serialize, Deserialize, Clone, Default)]
 pub struct Metadata {
     pub id: String,
     pub title: String,
     pub url: String,
+    /// URL the feed was fetched from.
+    #[serde(default)]
+    pub source: String,
+    #[serde(default)]
+    pub fetched_at: Option<DateTime<Utc>>,
+    /// Error from the last fetch, if it failed.
+    #[serde(default)]
+    pub fetch_error: Option<String>,
+    /// Subscription id on the sync server, for feeds pulled from one
+    /// instead of fetched directly.
+    #[serde(default)]
+    pub remote: Option<String>,
+    /// Category the sync server files the feed under.
+    #[serde(default)]
+    pub category: Option<String>,
 }
 
-#[derive(Serialize, Deserialize)]
+impl Metadata {
+    /// Whether `other` is the same feed. Feeds added before ids were stable
+    /// can have a different id on each machine, so a shared source counts
+    /// too.
+    pub fn same_feed(&self, other: &Metadata) -> bool {
+        self.id == other.id || (!self.source.is_empty() && self.source == other.source)
+    }
+}
+
+/// Format of a post's `content`, which decides how the reader parses it.
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentType {
+    #[default]
+    Html,
+    /// Well-formed XHTML, parsed as a fragment.
+    Xhtml,
+    /// Plain text; its line breaks are kept.
+    Text,
+    Markdown,
+}
+
+impl ContentType {
+    /// From an Atom `type` attribute: `text`, `html`, `xhtml` or a MIME
+    /// type. Without one the spec says text, but many feeds leave it out
+    /// of HTML content, so `content` is looked at instead.
+    pub fn from_atom(kind: Option<&str>, content: &str) -> ContentType {
+        let Some(kind) = kind else {
+            return ContentType::guess(content);
+        };
+        match kind {
+            "html" | "text/html" => ContentType::Html,
+            "xhtml" | "application/xhtml+xml" => ContentType::Xhtml,
+            "text/markdown" | "text/x-markdown" => ContentType::Markdown,
+            _ => ContentType::Text,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentType::Html => "html",
+            ContentType::Xhtml => "xhtml",
+            ContentType::Text => "text",
+            ContentType::Markdown => "markdown",
+        }
+    }
+
+    pub fn from_name(name: &str) -> ContentType {
+        match name {
+            "xhtml" => ContentType::Xhtml,
+            "text" => ContentType::Text,
+            "markdown" => ContentType::Markdown,
+            _ => ContentType::Html,
+        }
+    }
+
+    /// Guesses the type of an RSS description, which may be HTML or plain
+    /// text: it is HTML if it has any tags or entities.
+    pub fn guess(content: &str) -> ContentType {
+        static MARKUP: LazyLock<Regex> = LazyLock::new(|| {
+            Regex::new(r"</?[a-zA-Z][^>]*>|&(#[0-9]+|#x[0-9a-fA-F]+|[a-zA-Z]+);").unwrap()
+        });
+        if MARKUP.is_match(content) {
+            ContentType::Html
+        } else {
+            ContentType::Text
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct Post {
+    pub id: String,
     pub title: String,
+    pub link: String,
+    pub authors: Vec<String>,
+    pub categories: Vec<String>,
+    pub published: Option<DateTime<FixedOffset>>,
+    /// The body as the feed gave it, usually HTML. It is only converted
+    /// for display, when the reader draws it.
     pub content: String,
-    pub scroll: (u16, u16),
+    pub content_type: ContentType,
+    /// Image enclosures, shown above the content.
+    pub images: Vec<String>,
+    /// Full article extracted from the post's page, for feeds that only
+    /// give a teaser. Shown instead of `content` when present.
+    pub extracted: Option<String>,
+    pub read: bool,
+    pub starred: bool,
+    pub hidden: bool,
+    pub tags: Vec<String>,
+    /// Colour a rule gave the post, as written in the config. The UI turns
+    /// it into a terminal colour.
+    pub highlight: Option<String>,
+    /// When read/star/tag state last changed, for merging copies of the
+    /// store from different machines.
+    pub state_changed: Option<DateTime<Utc>>,
+    /// Whether the sync server had the post starred at the last sync, so a
+    /// push only flips stars that changed since. `None` if never synced.
+    pub server_starred: Option<bool>,
 }
 
 impl Post {
@@ -34,57 +138,160 @@ impl Post {
             Some(t) => t.to_string(),
             None => "title not found".to_string(),
         };
-        let html_content = match item.content() {
-            Some(t) => t.as_bytes(),
-            None => "Could not get content from post.".as_bytes(),
+        // A Markdown source (the `source:markdown` element) is preferred,
+        // then `content:encoded`, which is always HTML, then the
+        // description, which may be either HTML or plain text.
+        let markdown = item
+            .extensions()
+            .get("source")
+            .and_then(|source| source.get("markdown"))
+            .and_then(|elements| elements.first())
+            .and_then(|element| element.value());
+        let (content, content_type) = match (markdown, item.content(), item.description()) {
+            (Some(markdown), _, _) => (markdown, ContentType::Markdown),
+            (None, Some(content), _) => (content, ContentType::Html),
+            (None, None, Some(description)) => (description, ContentType::guess(description)),
+            (None, None, None) => ("Could not get content from post.", ContentType::Text),
+        };
+
+        let link = item.link().unwrap_or_default().to_string();
+        let id = match item.guid() {
+            Some(guid) => guid.value().to_string(),
+            None => link.clone(),
         };
-        let text_content = String::from_utf8(html_content.to_vec()).unwrap();
-        // let text_content = match from_read(html_content, usize::from(terminal::size().unwrap().0)) {
-        //     Ok(t) => t,
-        //     Err(e) => e.to_string(),
-        // };
+
+        let mut authors: Vec<String> = item.author().into_iter().map(String::from).collect();
+        if let Some(dc) = item.dublin_core_ext() {
+            authors.extend(dc.creators().iter().cloned());
+        }
 
         Ok(Post {
-            title: title,
-            content: text_content,
-            scroll: (0, 0),
+            id,
+            title,
+            link,
+            authors,
+            categories: item
+                .categories()
+                .iter()
+                .map(|c| c.name().to_string())
+                .collect(),
+            published: item
+                .pub_date()
+                .and_then(|d| DateTime::parse_from_rfc2822(d).ok()),
+            content: content.to_string(),
+            content_type,
+            images: item
+                .enclosure()
+                .filter(|e| e.mime_type().starts_with("image/"))
+                .map(|e| e.url().to_string())
+                .into_iter()
+                .collect(),
+            ..Default::default()
         })
     }
 
     pub fn from_atom(item: &Entry) -> Result<Post, Box<dyn Error>> {
         let title: String = item.title().to_string();
 
-        let html_content = match item.content() {
-            Some(t) => match t.value() {
-                Some(t) => t,
-                None => "Could not get value of content",
+        let (content, content_type) = match (item.content(), item.summary()) {
+            (Some(t), _) => {
+                let value = t.value().unwrap_or("Could not get value of content");
+                (value, ContentType::from_atom(t.content_type(), value))
             }
-            .as_bytes(),
-            None => "Could not get content from post.".as_bytes(),
-        };
-        let text_content = match from_read(html_content, usize::from(terminal::size().unwrap().0)) {
-            Ok(t) => t,
-            Err(e) => e.to_string(),
+            (None, Some(summary)) => (
+                summary.as_str(),
+                match summary.r#type {
+                    TextType::Text => ContentType::Text,
+                    TextType::Html => ContentType::Html,
+                    TextType::Xhtml => ContentType::Xhtml,
+                },
+            ),
+            (None, None) => ("Could not get content from post.", ContentType::Text),
         };
 
+        let link = item
+            .links()
+            .iter()
+            .find(|l| l.rel() == "alternate")
+            .or(item.links().first())
+            .map(|l| l.href().to_string())
+            .unwrap_or_default();
+
         Ok(Post {
+            id: item.id().to_string(),
             title: if title.is_empty() {
                 "[untitled]".to_string()
             } else {
                 title
             },
-            content: text_content,
-            scroll: (0, 0),
+            link,
+            authors: item
+                .authors()
+                .iter()
+                .map(|a| a.name().to_string())
+                .collect(),
+            categories: item
+                .categories()
+                .iter()
+                .map(|c| c.label().unwrap_or(c.term()).to_string())
+                .collect(),
+            published: Some(*item.published().unwrap_or(item.updated())),
+            content: content.to_string(),
+            content_type,
+            images: item
+                .links()
+                .iter()
+                .filter(|l| {
+                    l.rel() == "enclosure" && l.mime_type().is_some_and(|t| t.starts_with("image/"))
+                })
+                .map(|l| l.href().to_string())
+                .collect(),
+            ..Default::default()
         })
     }
 
-    pub fn scroll_up(&mut self) {
-        if self.scroll.0 > 0 {
-            self.scroll.0 -= 1
-        };
+    /// Identity used to recognise the post across refreshes. Falls back to
+    /// the title for feeds that give neither a guid nor a link.
+    pub fn key(&self) -> &str {
+        if self.id.is_empty() {
+            &self.title
+        } else {
+            &self.id
+        }
     }
-    pub fn scroll_down(&mut self) {
-        self.scroll.0 += 1
+
+    /// Records that the post's read/star/tag state changed just now.
+    pub fn touch(&mut self) {
+        self.state_changed = Some(Utc::now());
+    }
+
+    /// Copies the read/star/tag state from `other`. Hiding and highlights
+    /// come only from rules, so they aren't part of it.
+    pub fn copy_state(&mut self, other: &Post) {
+        self.read = other.read;
+        self.starred = other.starred;
+        self.tags = other.tags.clone();
+        self.state_changed = other.state_changed;
+        self.server_starred = other.server_starred;
+    }
+
+    /// Name of the post's author(s), if the feed provides any.
+    pub fn byline(&self) -> Option<String> {
+        if self.authors.is_empty() {
+            None
+        } else {
+            Some(self.authors.join(", "))
+        }
+    }
+
+    /// The version of the body the reader shows, and its type: the
+    /// extracted article if there is one, unless the feed's own content was
+    /// asked for.
+    pub fn displayed_content(&self, original: bool) -> (&str, ContentType) {
+        match &self.extracted {
+            Some(extracted) if !original => (extracted, ContentType::Html),
+            _ => (&self.content, self.content_type),
+        }
     }
 }
 
@@ -92,7 +299,10 @@ impl Post {
 pub struct RussFeed {
     pub meta: Metadata,
     pub posts: Vec<Post>,
-    pub state: ListState,
+    /// Keys of posts removed by the retention policy that the feed still
+    /// lists, so a refresh does not add them again.
+    #[serde(default)]
+    pub pruned: Vec<String>,
 }
 
 impl RussFeed {
@@ -102,16 +312,19 @@ impl RussFeed {
             posts.push(Post::from_item(item)?);
         }
 
-        let mut hasher = DefaultHasher::new();
-        channel.link().hash(&mut hasher);
         Ok(RussFeed {
             meta: Metadata {
-                id: hasher.finish().to_string(),
+                id: stable_id(channel.link()),
                 title: channel.title().to_string(),
                 url: channel.link().to_string(),
+                source: String::new(),
+                fetched_at: None,
+                fetch_error: None,
+                remote: None,
+                category: None,
             },
-            posts: posts,
-            state: ListState::default().with_selected(Some(0)),
+            posts,
+            pruned: Vec::new(),
         })
     }
 
@@ -121,41 +334,244 @@ impl RussFeed {
             posts.push(Post::from_atom(item)?)
         }
 
-        let mut hasher = DefaultHasher::new();
-        atom_feed.id().hash(&mut hasher);
-
         Ok(RussFeed {
             meta: Metadata {
-                id: hasher.finish().to_string(),
+                id: stable_id(atom_feed.id()),
                 title: atom_feed.title().to_string(),
                 url: atom_feed.id().to_string(),
+                source: String::new(),
+                fetched_at: None,
+                fetch_error: None,
+                remote: None,
+                category: None,
             },
-            posts: posts,
-            state: ListState::default().with_selected(Some(0)),
+            posts,
+            pruned: Vec::new(),
         })
     }
 
-    pub fn from_file(feed_id: String) -> Result<RussFeed, Box<dyn Error>> {
-        let mut path = env::home_dir().unwrap();
-        path.push(".russ/feeds/");
-        path.push(feed_id);
-        let content = fs::read_to_string(path)?;
-        Ok(serde_json::from_str(&content)?)
+    /// Merges a freshly fetched copy of this feed into it. Known posts get
+    /// their content updated but keep their read/star state; new posts are
+    /// added at the top, unless they were pruned before. Returns the number
+    /// of new posts.
+    pub fn merge(&mut self, fetched: RussFeed) -> usize {
+        self.meta.title = fetched.meta.title;
+        self.pruned
+            .retain(|key| fetched.posts.iter().any(|p| p.key() == key));
+
+        let mut new = Vec::new();
+        let mut seen = HashSet::new();
+        for post in fetched.posts {
+            // Feeds sometimes list an item twice; the first copy is kept.
+            if self.pruned.iter().any(|key| key == post.key())
+                || !seen.insert(post.key().to_string())
+            {
+                continue;
+            }
+            // Posts saved before ids were recorded are known by title only,
+            // and take on the id they are fetched with.
+            let existing = self
+                .posts
+                .iter()
+                .position(|p| p.key() == post.key())
+                .or_else(|| {
+                    self.posts
+                        .iter()
+                        .position(|p| p.id.is_empty() && p.link.is_empty() && p.title == post.title)
+                });
+            match existing.map(|i| &mut self.posts[i]) {
+                Some(existing) => {
+                    existing.id = post.id;
+                    existing.title = post.title;
+                    existing.link = post.link;
+                    existing.authors = post.authors;
+                    existing.categories = post.categories;
+                    existing.published = post.published;
+                    existing.content = post.content;
+                    existing.content_type = post.content_type;
+                    existing.images = post.images;
+                }
+                None => new.push(post),
+            }
+        }
+
+        let count = new.len();
+        self.posts.splice(0..0, new);
+        count
     }
 
-    pub fn save(&self) -> Result<(), Box<dyn Error>> {
-        let serialized = serde_json::to_string(&self).unwrap();
+    /// Merges another machine's copy of this feed. Posts are combined by
+    /// key, and for posts both copies have, whichever read/star/tag state
+    /// changed last wins, while a post hidden or highlighted in either copy
+    /// stays so.
+    pub fn merge_replica(&mut self, other: RussFeed) {
+        if other.meta.fetched_at > self.meta.fetched_at {
+            self.meta.title = other.meta.title;
+            self.meta.fetched_at = other.meta.fetched_at;
+            self.meta.fetch_error = other.meta.fetch_error;
+        }
+        for key in other.pruned {
+            if !self.pruned.contains(&key) {
+                self.pruned.push(key);
+            }
+        }
 
-        let mut hasher = DefaultHasher::new();
-        self.meta.url.hash(&mut hasher);
-        let mut path = env::home_dir().unwrap();
-        path.push(".russ/feeds/");
-        path.push(hasher.finish().to_string());
-        if !path.exists() {
-            let mut file = File::create(path)?;
-            file.write_all(serialized.as_ref())?;
+        let mut new = Vec::new();
+        for post in other.posts {
+            match self.posts.iter_mut().find(|p| p.key() == post.key()) {
+                Some(existing) => {
+                    if post.state_changed > existing.state_changed {
+                        existing.copy_state(&post);
+                    }
+                    // A rule that hid or highlighted the post on either
+                    // machine would do the same here.
+                    existing.hidden |= post.hidden;
+                    if existing.highlight.is_none() {
+                        existing.highlight = post.highlight;
+                    }
+                }
+                None => {
+                    if !self.pruned.iter().any(|key| key == post.key())
+                        && !new.iter().any(|p: &Post| p.key() == post.key())
+                    {
+                        new.push(post);
+                    }
+                }
+            }
         }
+        self.posts.splice(0..0, new);
+    }
+
+    pub fn from_file(path: &Path) -> Result<RussFeed, Box<dyn Error>> {
+        let content = fs::read_to_string(path)?;
+        let (mut feed, version): (RussFeed, u32) = json::from_str_versioned(&content)?;
+        if version == 0 {
+            feed.upgrade_legacy();
+        }
+        Ok(feed)
+    }
+
+    /// Brings a feed saved before versioning up to date. Its posts have no
+    /// id or link, which `merge` makes up for, and no content type: Atom
+    /// bodies were flattened to plain text by html2text, while RSS ones
+    /// kept their HTML.
+    fn upgrade_legacy(&mut self) {
+        for post in &mut self.posts {
+            post.content_type = ContentType::guess(&post.content);
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let serialized = json::to_string(self)?;
+        store::write_atomic(path, serialized.as_bytes())?;
 
         Ok(())
     }
 }
+
+/// FNV-1a hash of `s` as a decimal string. Unlike `DefaultHasher` it is the
+/// same on every machine and Rust version, so feed ids stay stable.
+pub fn stable_id(s: &str) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in s.bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A feed file as russ wrote it before posts had ids.
+    const LEGACY_FEED: &str = r#"{
+        "meta": {"id": "1", "title": "Blog", "url": "https://example.com/"},
+        "posts": [
+            {"title": "Flattened", "content": "Some text\n\n  * a list", "scroll": [0, 0]},
+            {"title": "Kept", "content": "<p>Some <b>HTML</b></p>", "scroll": [3, 0]}
+        ],
+        "state": {"offset": 0, "selected": 0}
+    }"#;
+
+    fn legacy_feed() -> RussFeed {
+        let path = std::env::temp_dir().join(format!("russ-legacy-{}.json", std::process::id()));
+        fs::write(&path, LEGACY_FEED).unwrap();
+        let feed = RussFeed::from_file(&path);
+        fs::remove_file(&path).unwrap();
+        feed.unwrap()
+    }
+
+    #[test]
+    fn reads_flattened_legacy_bodies_as_text() {
+        let feed = legacy_feed();
+        assert!(feed.posts[0].content_type == ContentType::Text);
+        assert!(feed.posts[1].content_type == ContentType::Html);
+    }
+
+    #[test]
+    fn legacy_posts_take_the_id_they_are_fetched_with() {
+        let mut feed = legacy_feed();
+        feed.posts[0].read = true;
+        let fetched = RussFeed {
+            meta: feed.meta.clone(),
+            posts: vec![Post {
+                id: "https://example.com/flattened".to_string(),
+                link: "https://example.com/flattened".to_string(),
+                title: "Flattened".to_string(),
+                ..Default::default()
+            }],
+            pruned: Vec::new(),
+        };
+
+        assert_eq!(feed.merge(fetched), 0);
+        assert_eq!(feed.posts.len(), 2);
+        assert_eq!(feed.posts[0].key(), "https://example.com/flattened");
+        assert!(feed.posts[0].read);
+    }
+
+    #[test]
+    fn replicas_keep_rule_state_from_either_copy() {
+        let mut feed = legacy_feed();
+        let mut other = legacy_feed();
+        feed.posts[0].read = true;
+        feed.posts[0].touch();
+        feed.posts[0].highlight = Some("red".to_string());
+        other.posts[0].hidden = true;
+        other.posts[0].highlight = Some("blue".to_string());
+        other.posts[1].highlight = Some("yellow".to_string());
+        other.posts[1].touch();
+
+        feed.merge_replica(other);
+        assert!(feed.posts[0].read && feed.posts[0].hidden);
+        assert_eq!(feed.posts[0].highlight.as_deref(), Some("red"));
+        assert_eq!(feed.posts[1].highlight.as_deref(), Some("yellow"));
+    }
+
+    #[test]
+    fn keeps_the_first_copy_of_a_repeated_item() {
+        let post = |title: &str| Post {
+            id: "https://example.com/1".to_string(),
+            title: title.to_string(),
+            ..Default::default()
+        };
+        let mut feed = legacy_feed();
+        let fetched = RussFeed {
+            meta: feed.meta.clone(),
+            posts: vec![post("First"), post("Second")],
+            pruned: Vec::new(),
+        };
+
+        assert_eq!(feed.merge(fetched), 1);
+        assert_eq!(feed.posts.len(), 3);
+        assert_eq!(feed.posts[0].title, "First");
+
+        let fetched = RussFeed {
+            meta: feed.meta.clone(),
+            posts: vec![post("Updated"), post("Second")],
+            pruned: Vec::new(),
+        };
+        assert_eq!(feed.merge(fetched), 0);
+        assert_eq!(feed.posts[0].title, "Updated");
+    }
+}
@@ -0,0 +1,114 @@
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::Span,
+};
+use std::sync::LazyLock;
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{self, FontStyle, ThemeSet},
+    parsing::{SyntaxReference, SyntaxSet},
+    util::LinesWithEndings,
+};
+
+static SYNTAXES: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
+static THEMES: LazyLock<ThemeSet> = LazyLock::new(ThemeSet::load_defaults);
+
+/// Columns a tab stands for in a code block.
+pub const TAB_WIDTH: usize = 4;
+
+/// Highlights `code` as `language` with the syntect theme `theme`, one
+/// `Vec` of spans per line. Returns `None` when the language or theme is
+/// unknown.
+pub fn highlight(code: &str, language: &str, theme: &str) -> Option<Vec<Vec<Span<'static>>>> {
+    let syntax = find_syntax(language)?;
+    let mut highlighter = HighlightLines::new(syntax, THEMES.themes.get(theme)?);
+
+    let code = code.replace('\t', &" ".repeat(TAB_WIDTH));
+    let mut lines = Vec::new();
+    for line in LinesWithEndings::from(code.trim_end_matches('\n')) {
+        let ranges = highlighter.highlight_line(line, &SYNTAXES).ok()?;
+        lines.push(
+            ranges
+                .into_iter()
+                .map(|(style, text)| {
+                    Span::styled(text.trim_end_matches('\n').to_string(), to_style(style))
+                })
+                .filter(|span| !span.content.is_empty())
+                .collect(),
+        );
+    }
+    Some(lines)
+}
+
+/// Language named by a code block's `class`, as written by most site
+/// generators: `language-rust`, `lang-rust`, `highlight-source-rust` or a
+/// bare `rust`.
+pub fn language_from_class(class: &str) -> Option<String> {
+    class.split_whitespace().find_map(|name| {
+        let name = ["language-", "lang-", "highlight-source-", "sourceCode-"]
+            .iter()
+            .find_map(|prefix| name.strip_prefix(prefix))
+            .unwrap_or(name);
+        find_syntax(name).map(|_| name.to_string())
+    })
+}
+
+/// Guesses the language of an unlabelled code block from a shebang or
+/// modeline, then from a few telltale keywords.
+pub fn guess_language(code: &str) -> Option<String> {
+    let first = code.lines().next().unwrap_or_default();
+    if let Some(syntax) = SYNTAXES.find_syntax_by_first_line(first) {
+        return Some(syntax.name.clone());
+    }
+
+    let hints: [(&str, &[&str]); 8] = [
+        ("rust", &["fn ", "let mut ", "impl ", "pub struct "]),
+        ("python", &["def ", "import ", "self."]),
+        ("go", &["func ", "package "]),
+        ("c", &["#include", "int main("]),
+        ("js", &["function ", "const ", "=> {", "console.log"]),
+        ("java", &["public class ", "System.out."]),
+        ("sh", &["$ ", "sudo ", "echo "]),
+        ("html", &["<div", "</"]),
+    ];
+    hints
+        .iter()
+        .map(|(language, words)| {
+            let hits = words.iter().filter(|w| code.contains(*w)).count();
+            (language, hits)
+        })
+        .filter(|(_, hits)| *hits >= 2)
+        .max_by_key(|(_, hits)| *hits)
+        .map(|(language, _)| language.to_string())
+}
+
+fn find_syntax(name: &str) -> Option<&'static SyntaxReference> {
+    let lower = name.to_lowercase();
+    let token = match lower.as_str() {
+        "shell" | "bash" | "zsh" | "console" => "sh",
+        "javascript" | "jsx" | "typescript" | "ts" | "tsx" => "js",
+        "golang" => "go",
+        "c++" => "cpp",
+        "yml" => "yaml",
+        other => other,
+    };
+    SYNTAXES
+        .find_syntax_by_token(token)
+        .or_else(|| SYNTAXES.find_syntax_by_name(name))
+        .filter(|syntax| syntax.name != "Plain Text")
+}
+
+fn to_style(style: highlighting::Style) -> Style {
+    let fg = style.foreground;
+    let mut result = Style::default().fg(Color::Rgb(fg.r, fg.g, fg.b));
+    if style.font_style.contains(FontStyle::BOLD) {
+        result = result.add_modifier(Modifier::BOLD);
+    }
+    if style.font_style.contains(FontStyle::ITALIC) {
+        result = result.add_modifier(Modifier::ITALIC);
+    }
+    if style.font_style.contains(FontStyle::UNDERLINE) {
+        result = result.add_modifier(Modifier::UNDERLINED);
+    }
+    result
+}
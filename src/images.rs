@@ -0,0 +1,194 @@
+use image::DynamicImage;
+use ratatui_image::{picker::Picker, protocol::StatefulProtocol};
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        LazyLock,
+        mpsc::{self, Receiver, Sender},
+    },
+    time::{Duration, SystemTime},
+};
+
+use crate::store;
+
+/// Largest image downloaded; bigger ones are shown by their alt text.
+const MAX_IMAGE_BYTES: u64 = 10 * 1024 * 1024;
+/// Size the cache directory is trimmed to on start, oldest downloads first.
+const MAX_CACHE_BYTES: u64 = 200 * 1024 * 1024;
+
+/// Client for image downloads, so a server that stalls leaves a
+/// placeholder rather than a task that never ends.
+static CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(20))
+        .build()
+        .unwrap_or_default()
+});
+
+enum State {
+    Loading,
+    Ready(Box<StatefulProtocol>),
+    Failed,
+}
+
+/// Images shown in the reader. Each is downloaded once into the cache
+/// directory and decoded in the background; the reader shows a placeholder
+/// until it is ready.
+pub struct Images {
+    picker: Picker,
+    dir: PathBuf,
+    states: HashMap<String, State>,
+    sender: Sender<(String, Option<DynamicImage>)>,
+    receiver: Receiver<(String, Option<DynamicImage>)>,
+}
+
+impl Images {
+    /// `picker` decides the graphics protocol (sixel, kitty, iTerm2 or
+    /// halfblocks) and must be made once the terminal is set up.
+    pub fn new(picker: Picker, dir: PathBuf) -> Images {
+        let cache = dir.clone();
+        tokio::task::spawn_blocking(move || trim_cache(&cache, MAX_CACHE_BYTES));
+        let (sender, receiver) = mpsc::channel();
+        Images {
+            picker,
+            dir,
+            states: HashMap::new(),
+            sender,
+            receiver,
+        }
+    }
+
+    /// Starts loading `url` unless it is loaded or loading already.
+    pub fn request(&mut self, url: &str) {
+        if self.states.contains_key(url) {
+            return;
+        }
+        self.states.insert(url.to_string(), State::Loading);
+
+        let sender = self.sender.clone();
+        let dir = self.dir.clone();
+        let url = url.to_string();
+        tokio::spawn(async move {
+            let image = load(&dir, &url).await.ok();
+            _ = sender.send((url, image));
+        });
+    }
+
+    /// Takes in the images that finished loading since the last call.
+    pub fn receive(&mut self) {
+        while let Ok((url, image)) = self.receiver.try_recv() {
+            let state = match image {
+                Some(image) => State::Ready(Box::new(self.picker.new_resize_protocol(image))),
+                None => State::Failed,
+            };
+            self.states.insert(url, state);
+        }
+    }
+
+    /// Whether any image is still on its way.
+    pub fn loading(&self) -> bool {
+        self.states.values().any(|s| matches!(s, State::Loading))
+    }
+
+    pub fn get(&mut self, url: &str) -> Option<&mut StatefulProtocol> {
+        match self.states.get_mut(url) {
+            Some(State::Ready(protocol)) => Some(protocol),
+            _ => None,
+        }
+    }
+}
+
+/// Reads `url` from the cache, downloading it first if needed, and decodes
+/// it off the async threads.
+async fn load(dir: &Path, url: &str) -> Result<DynamicImage, Box<dyn Error + Send + Sync>> {
+    let path = dir.join(format!("{:x}", md5::compute(url)));
+    let bytes = match fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(_) => {
+            let bytes = download(url).await?;
+            fs::create_dir_all(dir)?;
+            store::write_atomic(&path, &bytes)?;
+            bytes
+        }
+    };
+    let image = tokio::task::spawn_blocking(move || image::load_from_memory(&bytes)).await??;
+    Ok(image)
+}
+
+/// Downloads `url`, giving up once it is larger than `MAX_IMAGE_BYTES`.
+async fn download(url: &str) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    let mut response = CLIENT.get(url).send().await?.error_for_status()?;
+    if response
+        .content_length()
+        .is_some_and(|n| n > MAX_IMAGE_BYTES)
+    {
+        return Err(format!("{url} is too large").into());
+    }
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        bytes.extend_from_slice(&chunk);
+        if bytes.len() as u64 > MAX_IMAGE_BYTES {
+            return Err(format!("{url} is too large").into());
+        }
+    }
+    Ok(bytes)
+}
+
+/// Deletes the oldest files in `dir` until the rest fit in `max_bytes`.
+fn trim_cache(dir: &Path, max_bytes: u64) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut files: Vec<(SystemTime, u64, PathBuf)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let meta = entry.metadata().ok().filter(|m| m.is_file())?;
+            Some((meta.modified().ok()?, meta.len(), entry.path()))
+        })
+        .collect();
+    files.sort();
+
+    let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+    for (_, len, path) in files {
+        if total <= max_bytes {
+            break;
+        }
+        if fs::remove_file(path).is_ok() {
+            total -= len;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trims_the_oldest_files_first() {
+        let dir = std::env::temp_dir().join(format!("russ-images-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let now = SystemTime::now();
+        for (name, age) in [("old", 30), ("middle", 20), ("new", 10)] {
+            let path = dir.join(name);
+            fs::write(&path, [0; 100]).unwrap();
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(now - Duration::from_secs(age))
+                .unwrap();
+        }
+
+        trim_cache(&dir, 250);
+        let mut left: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        left.sort();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(left, ["middle", "new"]);
+    }
+}
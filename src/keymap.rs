@@ -0,0 +1,647 @@
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    fmt,
+    str::FromStr,
+};
+
+/// Where a key is pressed; each has its own bindings. Config tables are
+/// named after these: `[keys.main]`, `[keys.feed]`, `[keys.reader]` and
+/// `[keys.links]`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Context {
+    Main,
+    Feed,
+    Reader,
+    Links,
+}
+
+impl Context {
+    const ALL: [Context; 4] = [
+        Context::Main,
+        Context::Feed,
+        Context::Reader,
+        Context::Links,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Context::Main => "main",
+            Context::Feed => "feed",
+            Context::Reader => "reader",
+            Context::Links => "links",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    Next,
+    Prev,
+    Open,
+    Back,
+    PageDown,
+    PageUp,
+    HalfPageDown,
+    HalfPageUp,
+    Top,
+    Bottom,
+    ScrollLeft,
+    ScrollRight,
+    NextPost,
+    PrevPost,
+    NextUnread,
+    WidenPane,
+    NarrowPane,
+    Refresh,
+    MarkRead,
+    MarkUnread,
+    Star,
+    Tag,
+    Links,
+    Plain,
+    FullArticle,
+    CopyLink,
+    Subscribe,
+    Command,
+    Help,
+}
+
+/// Every action with its config name and what the help overlay says it
+/// does.
+const ACTIONS: &[(Action, &str, &str)] = &[
+    (Action::Next, "next", "move down"),
+    (Action::Prev, "prev", "move up"),
+    (Action::Open, "open", "open the selection"),
+    (Action::Back, "back", "go back, or quit from the main menu"),
+    (Action::PageDown, "page-down", "move down a page"),
+    (Action::PageUp, "page-up", "move up a page"),
+    (
+        Action::HalfPageDown,
+        "half-page-down",
+        "move down half a page",
+    ),
+    (Action::HalfPageUp, "half-page-up", "move up half a page"),
+    (Action::Top, "top", "go to the top, or to line N"),
+    (Action::Bottom, "bottom", "go to the bottom, or to line N"),
+    (Action::ScrollLeft, "scroll-left", "scroll code blocks left"),
+    (
+        Action::ScrollRight,
+        "scroll-right",
+        "scroll code blocks right",
+    ),
+    (Action::NextPost, "next-post", "open the next post"),
+    (Action::PrevPost, "prev-post", "open the previous post"),
+    (
+        Action::NextUnread,
+        "next-unread",
+        "go to the next unread post in any feed",
+    ),
+    (Action::WidenPane, "widen-pane", "widen the side pane"),
+    (Action::NarrowPane, "narrow-pane", "narrow the side pane"),
+    (Action::Refresh, "refresh", "fetch all feeds"),
+    (Action::MarkRead, "mark-read", "mark the post read"),
+    (Action::MarkUnread, "mark-unread", "mark the post unread"),
+    (Action::Star, "star", "star or unstar the post"),
+    (Action::Tag, "tag", "edit the post's tags"),
+    (Action::Links, "links", "list the article's links"),
+    (Action::Plain, "plain", "switch plain text on or off"),
+    (
+        Action::FullArticle,
+        "full-article",
+        "switch to the full article and back",
+    ),
+    (Action::CopyLink, "copy-link", "copy the link"),
+    (
+        Action::Subscribe,
+        "subscribe",
+        "subscribe to the link as a feed",
+    ),
+    (
+        Action::Command,
+        "command",
+        "type a command such as :messages",
+    ),
+    (Action::Help, "help", "show these keys"),
+];
+
+impl Action {
+    pub fn name(self) -> &'static str {
+        ACTIONS.iter().find(|(a, ..)| *a == self).unwrap().1
+    }
+
+    pub fn description(self) -> &'static str {
+        ACTIONS.iter().find(|(a, ..)| *a == self).unwrap().2
+    }
+
+    /// Whether a count repeats the action; for `top` and `bottom` it is a
+    /// line number instead, and other actions ignore it.
+    pub fn repeats(self) -> bool {
+        matches!(
+            self,
+            Action::Next
+                | Action::Prev
+                | Action::PageDown
+                | Action::PageUp
+                | Action::HalfPageDown
+                | Action::HalfPageUp
+                | Action::NextPost
+                | Action::PrevPost
+                | Action::WidenPane
+                | Action::NarrowPane
+                | Action::ScrollLeft
+                | Action::ScrollRight
+        )
+    }
+}
+
+impl FromStr for Action {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Action, Self::Err> {
+        ACTIONS
+            .iter()
+            .find(|(_, name, _)| *name == s)
+            .map(|(action, ..)| *action)
+            .ok_or_else(|| format!("Unknown action: {s}").into())
+    }
+}
+
+/// A key with its modifiers. Shift is part of the character for printable
+/// keys, so `G` is stored without it, and of `BackTab`, which is what
+/// terminals send for shift-tab.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Key {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl Key {
+    fn new(mut code: KeyCode, mut modifiers: KeyModifiers) -> Key {
+        if code == KeyCode::Tab && modifiers.contains(KeyModifiers::SHIFT) {
+            code = KeyCode::BackTab;
+        }
+        if matches!(code, KeyCode::Char(_) | KeyCode::BackTab) {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        Key { code, modifiers }
+    }
+
+    fn char(c: char) -> Key {
+        Key::new(KeyCode::Char(c), KeyModifiers::NONE)
+    }
+}
+
+impl From<KeyEvent> for Key {
+    fn from(event: KeyEvent) -> Key {
+        Key::new(event.code, event.modifiers)
+    }
+}
+
+/// Named keys as written between angle brackets.
+const KEY_NAMES: &[(&str, KeyCode)] = &[
+    ("Enter", KeyCode::Enter),
+    ("Esc", KeyCode::Esc),
+    ("Space", KeyCode::Char(' ')),
+    ("Tab", KeyCode::Tab),
+    ("BS", KeyCode::Backspace),
+    ("Del", KeyCode::Delete),
+    ("Up", KeyCode::Up),
+    ("Down", KeyCode::Down),
+    ("Left", KeyCode::Left),
+    ("Right", KeyCode::Right),
+    ("PageUp", KeyCode::PageUp),
+    ("PageDown", KeyCode::PageDown),
+    ("Home", KeyCode::Home),
+    ("End", KeyCode::End),
+    ("lt", KeyCode::Char('<')),
+];
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = KEY_NAMES.iter().find(|(_, code)| *code == self.code);
+        let mut prefix = String::new();
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            prefix.push_str("C-");
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            prefix.push_str("A-");
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            prefix.push_str("S-");
+        }
+        match (name, self.code) {
+            (None, KeyCode::Char(c)) if prefix.is_empty() => write!(f, "{c}"),
+            (None, KeyCode::Char(c)) => write!(f, "<{prefix}{c}>"),
+            (Some((name, _)), _) => write!(f, "<{prefix}{name}>"),
+            (None, KeyCode::BackTab) => write!(f, "<{prefix}S-Tab>"),
+            (None, code) => write!(f, "<{prefix}{code}>"),
+        }
+    }
+}
+
+/// Parses a key sequence in Vim notation: `gg`, `G`, `<C-d>`, `<Space>`,
+/// `<S-Tab>`. A `<` that doesn't start a key name stands for itself.
+pub fn parse_keys(s: &str) -> Result<Vec<Key>, Box<dyn Error>> {
+    let mut keys = Vec::new();
+    let mut rest = s;
+    while let Some(c) = rest.chars().next() {
+        let special = (c == '<')
+            .then(|| rest[1..].split_once('>'))
+            .flatten()
+            .filter(|(inner, _)| !inner.is_empty());
+        match special {
+            Some((inner, after)) => {
+                keys.push(parse_special(inner).ok_or(format!("Unknown key: <{inner}>"))?);
+                rest = after;
+            }
+            None => {
+                keys.push(Key::char(c));
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    if keys.is_empty() {
+        return Err("Empty key binding".into());
+    }
+    Ok(keys)
+}
+
+/// The inside of `<...>`: modifiers, then a key name or a single character.
+fn parse_special(mut inner: &str) -> Option<Key> {
+    let mut modifiers = KeyModifiers::NONE;
+    while let Some(prefix) = inner.get(..2) {
+        let modifier = match prefix {
+            "C-" | "c-" => KeyModifiers::CONTROL,
+            "A-" | "a-" | "M-" | "m-" => KeyModifiers::ALT,
+            "S-" | "s-" => KeyModifiers::SHIFT,
+            _ => break,
+        };
+        if inner.len() == 2 {
+            break;
+        }
+        modifiers |= modifier;
+        inner = &inner[2..];
+    }
+
+    let mut chars = inner.chars();
+    let code = match (chars.next(), chars.next()) {
+        (Some(c), None) => KeyCode::Char(c),
+        _ => {
+            let (_, code) = KEY_NAMES
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(inner))?;
+            *code
+        }
+    };
+    Some(Key::new(code, modifiers))
+}
+
+/// Bindings shipped with russ, before the config's overrides.
+const DEFAULT_BINDINGS: &[(Context, &str, Action)] = &[
+    (Context::Main, "j", Action::Next),
+    (Context::Main, "<Down>", Action::Next),
+    (Context::Main, "k", Action::Prev),
+    (Context::Main, "<Up>", Action::Prev),
+    (Context::Main, "l", Action::Open),
+    (Context::Main, "<Enter>", Action::Open),
+    (Context::Main, "q", Action::Back),
+    (Context::Main, "<C-f>", Action::PageDown),
+    (Context::Main, "<PageDown>", Action::PageDown),
+    (Context::Main, "<C-b>", Action::PageUp),
+    (Context::Main, "<PageUp>", Action::PageUp),
+    (Context::Main, "<C-d>", Action::HalfPageDown),
+    (Context::Main, "<C-u>", Action::HalfPageUp),
+    (Context::Main, "<Home>", Action::Top),
+    (Context::Main, "<End>", Action::Bottom),
+    (Context::Main, "<Space>", Action::PageDown),
+    (Context::Main, "n", Action::NextUnread),
+    (Context::Main, "gg", Action::Top),
+    (Context::Main, "G", Action::Bottom),
+    (Context::Main, "+", Action::WidenPane),
+    (Context::Main, "-", Action::NarrowPane),
+    (Context::Main, "R", Action::Refresh),
+    (Context::Main, ":", Action::Command),
+    (Context::Main, "?", Action::Help),
+    (Context::Feed, "j", Action::Next),
+    (Context::Feed, "<Down>", Action::Next),
+    (Context::Feed, "k", Action::Prev),
+    (Context::Feed, "<Up>", Action::Prev),
+    (Context::Feed, "l", Action::Open),
+    (Context::Feed, "<Enter>", Action::Open),
+    (Context::Feed, "h", Action::Back),
+    (Context::Feed, "q", Action::Back),
+    (Context::Feed, "<C-f>", Action::PageDown),
+    (Context::Feed, "<PageDown>", Action::PageDown),
+    (Context::Feed, "<C-b>", Action::PageUp),
+    (Context::Feed, "<PageUp>", Action::PageUp),
+    (Context::Feed, "<C-d>", Action::HalfPageDown),
+    (Context::Feed, "<C-u>", Action::HalfPageUp),
+    (Context::Feed, "<Home>", Action::Top),
+    (Context::Feed, "<End>", Action::Bottom),
+    (Context::Feed, "<Space>", Action::PageDown),
+    (Context::Feed, "n", Action::NextUnread),
+    (Context::Feed, "gg", Action::Top),
+    (Context::Feed, "G", Action::Bottom),
+    (Context::Feed, "s", Action::Star),
+    (Context::Feed, "t", Action::Tag),
+    (Context::Feed, "r", Action::MarkRead),
+    (Context::Feed, "u", Action::MarkUnread),
+    (Context::Feed, "+", Action::WidenPane),
+    (Context::Feed, "-", Action::NarrowPane),
+    (Context::Feed, "R", Action::Refresh),
+    (Context::Feed, ":", Action::Command),
+    (Context::Feed, "?", Action::Help),
+    (Context::Reader, "j", Action::Next),
+    (Context::Reader, "<Down>", Action::Next),
+    (Context::Reader, "k", Action::Prev),
+    (Context::Reader, "<Up>", Action::Prev),
+    (Context::Reader, "h", Action::Back),
+    (Context::Reader, "q", Action::Back),
+    (Context::Reader, "<C-f>", Action::PageDown),
+    (Context::Reader, "<PageDown>", Action::PageDown),
+    (Context::Reader, "<Space>", Action::PageDown),
+    (Context::Reader, "<C-b>", Action::PageUp),
+    (Context::Reader, "<PageUp>", Action::PageUp),
+    (Context::Reader, "<C-d>", Action::HalfPageDown),
+    (Context::Reader, "<C-u>", Action::HalfPageUp),
+    (Context::Reader, "<Home>", Action::Top),
+    (Context::Reader, "<End>", Action::Bottom),
+    (Context::Reader, "J", Action::NextPost),
+    (Context::Reader, "K", Action::PrevPost),
+    (Context::Reader, "n", Action::NextUnread),
+    (Context::Reader, "gg", Action::Top),
+    (Context::Reader, "G", Action::Bottom),
+    (Context::Reader, "<lt>", Action::ScrollLeft),
+    (Context::Reader, "<Left>", Action::ScrollLeft),
+    (Context::Reader, ">", Action::ScrollRight),
+    (Context::Reader, "<Right>", Action::ScrollRight),
+    (Context::Reader, "s", Action::Star),
+    (Context::Reader, "t", Action::Tag),
+    (Context::Reader, "u", Action::MarkUnread),
+    (Context::Reader, "f", Action::Links),
+    (Context::Reader, "p", Action::Plain),
+    (Context::Reader, "e", Action::FullArticle),
+    (Context::Reader, "+", Action::WidenPane),
+    (Context::Reader, "-", Action::NarrowPane),
+    (Context::Reader, ":", Action::Command),
+    (Context::Reader, "?", Action::Help),
+    (Context::Links, "j", Action::Next),
+    (Context::Links, "<Down>", Action::Next),
+    (Context::Links, "k", Action::Prev),
+    (Context::Links, "<Up>", Action::Prev),
+    (Context::Links, "<C-d>", Action::HalfPageDown),
+    (Context::Links, "<C-u>", Action::HalfPageUp),
+    (Context::Links, "<Home>", Action::Top),
+    (Context::Links, "<End>", Action::Bottom),
+    (Context::Links, "<Enter>", Action::Open),
+    (Context::Links, "o", Action::Open),
+    (Context::Links, "q", Action::Back),
+    (Context::Links, "<Esc>", Action::Back),
+    (Context::Links, "y", Action::CopyLink),
+    (Context::Links, "a", Action::Subscribe),
+    (Context::Links, "?", Action::Help),
+];
+
+/// Key bindings for each context, and the keys typed so far towards a
+/// sequence or a count.
+pub struct Keymap {
+    bindings: HashMap<Context, Vec<(Vec<Key>, Action)>>,
+    pending: Vec<Key>,
+    count: Option<usize>,
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        let mut bindings: HashMap<Context, Vec<(Vec<Key>, Action)>> = HashMap::new();
+        for (context, keys, action) in DEFAULT_BINDINGS {
+            bindings
+                .entry(*context)
+                .or_default()
+                .push((parse_keys(keys).unwrap(), *action));
+        }
+        Keymap {
+            bindings,
+            pending: Vec::new(),
+            count: None,
+        }
+    }
+}
+
+impl Keymap {
+    /// The default bindings with the config's on top. `keys` maps context
+    /// names to tables of key sequences and action names; the action
+    /// `none` unbinds a sequence:
+    ///
+    /// ```toml
+    /// [keys.reader]
+    /// "d" = "half-page-down"
+    /// "<Space>" = "none"
+    /// ```
+    pub fn from_config(
+        keys: &BTreeMap<String, BTreeMap<String, String>>,
+    ) -> Result<Keymap, Box<dyn Error>> {
+        let mut keymap = Keymap::default();
+        for (context_name, table) in keys {
+            let context = Context::ALL
+                .into_iter()
+                .find(|c| c.name() == context_name)
+                .ok_or(format!("Unknown key context: {context_name}"))?;
+            let bindings = keymap.bindings.entry(context).or_default();
+            for (sequence, action) in table {
+                let sequence = parse_keys(sequence)?;
+                bindings.retain(|(keys, _)| *keys != sequence);
+                if action != "none" {
+                    bindings.push((sequence, action.parse()?));
+                }
+            }
+        }
+        Ok(keymap)
+    }
+
+    /// Takes one key press in `context`. Returns the action once a bound
+    /// sequence is complete, with the count typed before it, if any. Digits
+    /// before a sequence are a count; a key that neither completes nor
+    /// continues a sequence starts over.
+    pub fn press(&mut self, context: Context, event: KeyEvent) -> Option<(Action, Option<usize>)> {
+        let key = Key::from(event);
+        if let KeyCode::Char(c @ '0'..='9') = key.code
+            && self.pending.is_empty()
+            && key.modifiers.is_empty()
+            && (c != '0' || self.count.is_some())
+        {
+            let digit = c.to_digit(10).unwrap() as usize;
+            self.count = Some(
+                self.count
+                    .unwrap_or(0)
+                    .saturating_mul(10)
+                    .saturating_add(digit),
+            );
+            return None;
+        }
+
+        self.pending.push(key);
+        let bindings = self
+            .bindings
+            .get(&context)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        if let Some((_, action)) = bindings.iter().find(|(keys, _)| *keys == self.pending) {
+            self.pending.clear();
+            return Some((*action, self.count.take()));
+        }
+        if bindings
+            .iter()
+            .any(|(keys, _)| keys.starts_with(&self.pending))
+        {
+            return None;
+        }
+
+        let retry = self.pending.len() > 1;
+        self.pending.clear();
+        self.count = None;
+        if retry {
+            self.press(context, event)
+        } else {
+            None
+        }
+    }
+
+    /// The bindings of `context` grouped by action, in the order the actions
+    /// were first bound: what the help overlay lists.
+    pub fn help(&self, context: Context) -> Vec<(String, Action)> {
+        let mut help: Vec<(String, Action)> = Vec::new();
+        for (keys, action) in self.bindings.get(&context).into_iter().flatten() {
+            let keys: String = keys.iter().map(Key::to_string).collect();
+            match help.iter_mut().find(|(_, a)| a == action) {
+                Some((all, _)) => {
+                    all.push_str(", ");
+                    all.push_str(&keys);
+                }
+                None => help.push((keys, *action)),
+            }
+        }
+        help
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(c: char) -> KeyEvent {
+        KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE)
+    }
+
+    /// What each key of `keys` returns when typed in `context`.
+    fn type_keys(
+        keymap: &mut Keymap,
+        context: Context,
+        keys: &str,
+    ) -> Vec<Option<(Action, Option<usize>)>> {
+        keys.chars()
+            .map(|c| keymap.press(context, event(c)))
+            .collect()
+    }
+
+    #[test]
+    fn parses_vim_notation() {
+        assert!(parse_keys("gg").unwrap() == [Key::char('g'), Key::char('g')]);
+        assert!(parse_keys("G").unwrap() == [Key::char('G')]);
+        assert!(
+            parse_keys("<C-d>").unwrap() == [Key::new(KeyCode::Char('d'), KeyModifiers::CONTROL)]
+        );
+        assert!(parse_keys("<S-Tab>").unwrap() == [Key::new(KeyCode::BackTab, KeyModifiers::NONE)]);
+        assert!(parse_keys("<space>").unwrap() == [Key::char(' ')]);
+        assert!(parse_keys("<lt>").unwrap() == [Key::char('<')]);
+        assert!(parse_keys("<").unwrap() == [Key::char('<')]);
+        assert!(parse_keys("<>").unwrap() == [Key::char('<'), Key::char('>')]);
+        assert!(parse_keys("<Nope>").is_err());
+        assert!(parse_keys("").is_err());
+    }
+
+    #[test]
+    fn counts_before_an_action() {
+        let mut keymap = Keymap::default();
+        assert_eq!(
+            type_keys(&mut keymap, Context::Main, "12j"),
+            [None, None, Some((Action::Next, Some(12)))]
+        );
+        assert_eq!(
+            type_keys(&mut keymap, Context::Main, "j"),
+            [Some((Action::Next, None))]
+        );
+        // A leading zero isn't a count, and isn't bound.
+        assert_eq!(
+            type_keys(&mut keymap, Context::Main, "0j"),
+            [None, Some((Action::Next, None))]
+        );
+    }
+
+    #[test]
+    fn completes_sequences() {
+        let mut keymap = Keymap::default();
+        assert_eq!(
+            type_keys(&mut keymap, Context::Reader, "3gg"),
+            [None, None, Some((Action::Top, Some(3)))]
+        );
+    }
+
+    #[test]
+    fn retries_a_key_that_breaks_a_sequence() {
+        let mut keymap = Keymap::default();
+        assert_eq!(
+            type_keys(&mut keymap, Context::Reader, "2gj"),
+            [None, None, Some((Action::Next, None))]
+        );
+        assert_eq!(type_keys(&mut keymap, Context::Reader, "gx"), [None, None]);
+    }
+
+    #[test]
+    fn config_rebinds_and_unbinds() {
+        let keys = BTreeMap::from([(
+            "reader".to_string(),
+            BTreeMap::from([
+                ("<Space>".to_string(), "none".to_string()),
+                ("d".to_string(), "half-page-down".to_string()),
+            ]),
+        )]);
+        let mut keymap = Keymap::from_config(&keys).unwrap();
+        assert_eq!(type_keys(&mut keymap, Context::Reader, " "), [None]);
+        assert_eq!(
+            type_keys(&mut keymap, Context::Reader, "d"),
+            [Some((Action::HalfPageDown, None))]
+        );
+        // Only the reader's bindings changed.
+        assert_eq!(
+            type_keys(&mut keymap, Context::Feed, " "),
+            [Some((Action::PageDown, None))]
+        );
+
+        let unknown = BTreeMap::from([("nowhere".to_string(), BTreeMap::new())]);
+        assert!(Keymap::from_config(&unknown).is_err());
+    }
+
+    #[test]
+    fn binds_shift_tab_as_terminals_send_it() {
+        let keys = BTreeMap::from([(
+            "reader".to_string(),
+            BTreeMap::from([
+                ("z".to_string(), "links".to_string()),
+                ("<S-Tab>".to_string(), "links".to_string()),
+            ]),
+        )]);
+        let mut keymap = Keymap::from_config(&keys).unwrap();
+        let back_tab = KeyEvent::new(KeyCode::BackTab, KeyModifiers::SHIFT);
+        assert_eq!(
+            keymap.press(Context::Reader, back_tab),
+            Some((Action::Links, None))
+        );
+        assert!(
+            keymap
+                .help(Context::Reader)
+                .contains(&("f, <S-Tab>, z".to_string(), Action::Links))
+        );
+    }
+}
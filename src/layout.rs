@@ -0,0 +1,157 @@
+use ratatui::layout::{Constraint, Direction, Layout as Split, Rect};
+use serde::Deserialize;
+
+use crate::app::Screen;
+
+/// Columns a pane grows or shrinks by per key press.
+const RESIZE_STEP: u16 = 4;
+/// Narrowest a resizable pane gets.
+const MIN_PANE: u16 = 12;
+/// Narrowest the pane next to a resizable one gets.
+const MIN_REST: u16 = 20;
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// Picked from the terminal width.
+    #[default]
+    Auto,
+    /// Feeds and posts, or posts and the article in the reader.
+    Two,
+    /// Feeds, posts and the article side by side.
+    Three,
+    /// One pane at a time, across the whole width.
+    Stacked,
+}
+
+/// The `[layout]` table of the config.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct LayoutConfig {
+    pub mode: Mode,
+    /// Terminal width from which `auto` shows three panes.
+    pub three_pane_width: u16,
+    /// Terminal width below which `auto` stacks the panes.
+    pub stacked_width: u16,
+    pub feeds_width: u16,
+    pub posts_width: u16,
+    /// Widest the article's text gets; it is centred in the reader.
+    pub max_text_width: u16,
+}
+
+impl Default for LayoutConfig {
+    fn default() -> LayoutConfig {
+        LayoutConfig {
+            mode: Mode::Auto,
+            three_pane_width: 160,
+            stacked_width: 80,
+            feeds_width: 40,
+            posts_width: 60,
+            max_text_width: 100,
+        }
+    }
+}
+
+/// Where each pane goes in a frame. Panes that aren't shown are `None`.
+#[derive(Default)]
+pub struct Panes {
+    pub feeds: Option<Rect>,
+    pub posts: Option<Rect>,
+    pub article: Option<Rect>,
+}
+
+/// Splits the screen into panes for the current screen and terminal
+/// width. Pane widths start from the config and change with
+/// `widen-pane`/`narrow-pane`.
+pub struct Layout {
+    config: LayoutConfig,
+    /// Width of the last frame, to tell which mode `auto` is in.
+    width: u16,
+}
+
+impl Layout {
+    pub fn new(config: LayoutConfig) -> Layout {
+        Layout { config, width: 0 }
+    }
+
+    pub fn max_text_width(&self) -> u16 {
+        self.config.max_text_width
+    }
+
+    /// The mode in use at the last frame's width.
+    fn mode(&self) -> Mode {
+        match self.config.mode {
+            Mode::Auto if self.width >= self.config.three_pane_width => Mode::Three,
+            Mode::Auto if self.width < self.config.stacked_width => Mode::Stacked,
+            Mode::Auto => Mode::Two,
+            mode => mode,
+        }
+    }
+
+    pub fn split(&mut self, area: Rect, screen: &Screen) -> Panes {
+        self.width = area.width;
+        let feeds = Constraint::Length(self.config.feeds_width);
+        let posts = Constraint::Length(self.config.posts_width);
+        let rest = Constraint::Min(MIN_REST);
+        let columns = |constraints: &[Constraint]| {
+            Split::default()
+                .direction(Direction::Horizontal)
+                .constraints(constraints)
+                .split(area)
+        };
+
+        match (self.mode(), screen) {
+            (Mode::Three, _) => {
+                let c = columns(&[feeds, posts, rest]);
+                Panes {
+                    feeds: Some(c[0]),
+                    posts: Some(c[1]),
+                    article: Some(c[2]),
+                }
+            }
+            (Mode::Stacked, Screen::Reader) => Panes {
+                article: Some(area),
+                ..Default::default()
+            },
+            (Mode::Stacked, Screen::FeedMenu) => Panes {
+                posts: Some(area),
+                ..Default::default()
+            },
+            (Mode::Stacked, _) => Panes {
+                feeds: Some(area),
+                ..Default::default()
+            },
+            (_, Screen::Reader) => {
+                let c = columns(&[posts, rest]);
+                Panes {
+                    posts: Some(c[0]),
+                    article: Some(c[1]),
+                    ..Default::default()
+                }
+            }
+            _ => {
+                let c = columns(&[feeds, rest]);
+                Panes {
+                    feeds: Some(c[0]),
+                    posts: Some(c[1]),
+                    ..Default::default()
+                }
+            }
+        }
+    }
+
+    /// Widens the pane that sets the split on `screen` by `steps`, or
+    /// narrows it when negative: the feeds in the menus, the posts next to
+    /// the reader, and the posts in the three-pane feed list.
+    pub fn resize(&mut self, screen: &Screen, steps: i16) {
+        let pane = match (self.mode(), screen) {
+            (Mode::Stacked, _) => return,
+            (Mode::Three, Screen::MainMenu) => &mut self.config.feeds_width,
+            (Mode::Three, _) | (_, Screen::Reader) => &mut self.config.posts_width,
+            _ => &mut self.config.feeds_width,
+        };
+        let max = self.width.saturating_sub(MIN_REST).max(MIN_PANE);
+        let width = i32::from(*pane) + i32::from(steps) * i32::from(RESIZE_STEP);
+        *pane = width.clamp(i32::from(MIN_PANE), i32::from(max)) as u16;
+    }
+}
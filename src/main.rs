@@ -1,39 +1,94 @@
-use std::{error::Error, io};
+use ratatui_image::picker::Picker;
+use std::{env, error::Error, io, time::Duration};
 
 use ratatui::{
     Terminal,
     backend::{Backend, CrosstermBackend},
     crossterm::{
-        event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind},
+        event::{
+            self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind,
+            MouseButton, MouseEvent, MouseEventKind,
+        },
         execute,
         terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
     },
+    layout::{Position, Rect},
+    widgets::ListState,
 };
 
 mod app;
+mod cli;
 mod feed;
+mod highlight;
+mod images;
+mod keymap;
+mod layout;
+mod messages;
+mod query;
+mod readability;
+mod retention;
+mod rules;
+mod sanitize;
+mod session;
+mod store;
 mod styling;
+mod sync;
+mod theme;
 mod ui;
 
 use crate::{
-    app::{App, Screen},
+    app::{App, PromptKind, Screen},
+    images::Images,
+    keymap::{Action, Context},
+    session::Session,
     ui::ui,
 };
 
+/// Cell size in pixels assumed when the terminal does not report it.
+const FONT_SIZE: (u16, u16) = (8, 16);
+
+/// How often to redraw while images are loading.
+const IMAGE_POLL: Duration = Duration::from_millis(100);
+
+/// Rows the reader scrolls per notch of the mouse wheel.
+const WHEEL_ROWS: u16 = 3;
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     let mut app = App::new();
+    app.load_config()?;
+
+    // Subcommands open the store themselves, and only fetch what they need.
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Some(command) = args.first() {
+        return cli::run(&mut app, command, &args[1..]).await;
+    }
+
+    app.open_store()?;
+    app.add_config_feeds().await?;
+    app.update_folders();
 
-    app.load_from_config().await?;
-    app.load_all()?;
+    if app.config.restore_session {
+        app.restore(Session::load(&app.paths.session()));
+    }
 
     enable_raw_mode()?;
     let mut stderr = io::stderr();
-    execute!(stderr, EnterAlternateScreen, EnableMouseCapture)?;
+    execute!(stderr, EnterAlternateScreen)?;
+    if app.config.mouse {
+        execute!(stderr, EnableMouseCapture)?;
+    }
 
     let backend = CrosstermBackend::new(stderr);
     let mut terminal = Terminal::new(backend)?;
 
+    if app.config.images {
+        // Asks the terminal which graphics protocol it speaks; without an
+        // answer images are drawn with half blocks.
+        let picker = Picker::from_query_stdio().unwrap_or(Picker::from_fontsize(FONT_SIZE));
+        app.images = Some(Images::new(picker, app.paths.images()));
+    }
+
     let res = run_app(&mut terminal, &mut app);
 
     disable_raw_mode()?;
@@ -44,6 +99,10 @@ async fn main() -> Result<(), Box<dyn Error>> {
     )?;
     terminal.show_cursor()?;
 
+    if app.config.restore_session {
+        app.session().save(&app.paths.session())?;
+    }
+
     if let Ok(do_print) = res {
         if do_print {}
     } else if let Err(err) = res {
@@ -57,69 +116,321 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> io::Result<
     loop {
         terminal.draw(|f| ui(f, app))?;
 
-        if let Event::Key(key) = event::read()? {
-            if key.kind == KeyEventKind::Release {
+        // Fetch the next feed of a refresh unless a key is waiting.
+        if app.refreshing.is_some() && !event::poll(Duration::ZERO)? {
+            app.refresh_step();
+            continue;
+        }
+
+        // Redraw when an image arrives, not only on input.
+        if app.images.as_ref().is_some_and(Images::loading) && !event::poll(IMAGE_POLL)? {
+            continue;
+        }
+        // Redraw when the message in the status bar is up.
+        if let Some(remaining) = app.messages.remaining()
+            && !event::poll(remaining)?
+        {
+            continue;
+        }
+
+        let key = match event::read()? {
+            Event::Key(key) => key,
+            Event::Mouse(event) => {
+                mouse(app, event);
                 continue;
             }
-            match app.current_screen {
-                Screen::MainMenu => match key.code {
-                    KeyCode::Char('q') => {
-                        app.current_screen = Screen::Exiting;
-                    }
-                    KeyCode::Char('j') => app.index.state.select_next(),
-                    KeyCode::Char('k') => app.index.state.select_previous(),
-                    KeyCode::Char('l') => app.current_screen = Screen::FeedMenu,
-                    KeyCode::Enter => {
-                        app.current_screen = Screen::FeedMenu;
-                    }
-                    _ => {}
-                },
-                Screen::Exiting => match key.code {
-                    KeyCode::Char('y') => {
-                        return Ok(true);
-                    }
-                    KeyCode::Char('n') | KeyCode::Char('q') => {
-                        return Ok(false);
-                    }
-                    _ => {}
-                },
-                Screen::Reader => match key.code {
-                    KeyCode::Char('q') => {
-                        app.current_screen = Screen::FeedMenu;
-                    }
-                    KeyCode::Char('j') => {
-                        let ch = app.index.state.selected().unwrap();
-                        let p = app.feeds[ch].state.selected().unwrap();
-                        app.feeds[ch].posts[p].scroll_down();
-                    }
-                    KeyCode::Char('k') => {
-                        let ch = app.index.state.selected().unwrap();
-                        let p = app.feeds[ch].state.selected().unwrap();
-                        app.feeds[ch].posts[p].scroll_up();
-                    }
-                    KeyCode::Char('h') => app.current_screen = Screen::FeedMenu,
-                    _ => {}
-                },
-                Screen::FeedMenu => match key.code {
-                    KeyCode::Char('h') => app.current_screen = Screen::MainMenu,
-                    KeyCode::Char('l') => app.current_screen = Screen::Reader,
-                    KeyCode::Char('q') => {
-                        app.current_screen = Screen::MainMenu;
-                    }
-                    KeyCode::Char('j') => {
-                        let ch = app.index.state.selected().unwrap();
-                        app.feeds[ch].state.select_next();
-                    }
-                    KeyCode::Char('k') => {
-                        let ch = app.index.state.selected().unwrap();
-                        app.feeds[ch].state.select_previous();
+            _ => continue,
+        };
+        if key.kind == KeyEventKind::Release {
+            continue;
+        }
+        if let Some(prompt) = &mut app.prompt {
+            match key.code {
+                KeyCode::Enter => app.submit_prompt(),
+                KeyCode::Esc => app.prompt = None,
+                KeyCode::Backspace => {
+                    prompt.text.pop();
+                }
+                KeyCode::Char(c) => prompt.text.push(c),
+                _ => {}
+            }
+            continue;
+        }
+        if app.help || app.log {
+            app.help = false;
+            app.log = false;
+            continue;
+        }
+        if let Some(picker) = &mut app.picker
+            && let KeyCode::Char(c @ '0'..='9') = key.code
+        {
+            picker.type_digit(c);
+            continue;
+        }
+        let Some(context) = app.key_context() else {
+            match key.code {
+                KeyCode::Char('y') => return Ok(true),
+                KeyCode::Char('n') | KeyCode::Char('q') => return Ok(false),
+                _ => {}
+            }
+            continue;
+        };
+        if let Some((action, count)) = app.keymap.press(context, key) {
+            perform(app, context, action, count);
+        }
+    }
+}
+
+/// Carries out a bound action. `count` repeats movements and is a line
+/// number for `top` and `bottom`.
+fn perform(app: &mut App, context: Context, action: Action, count: Option<usize>) {
+    let times = if action.repeats() {
+        count.unwrap_or(1)
+    } else {
+        1
+    };
+    let rows = u16::try_from(times).unwrap_or(u16::MAX);
+    let page = app.view.page_rows.max(1).saturating_mul(rows);
+    let half_page = (app.view.page_rows / 2).max(1).saturating_mul(rows);
+
+    match action {
+        Action::Help => app.help = true,
+        Action::Command => app.start_prompt(PromptKind::Command),
+        Action::Refresh => app.start_refresh(),
+        Action::WidenPane | Action::NarrowPane => {
+            let steps = i16::try_from(rows).unwrap_or(i16::MAX);
+            let steps = if action == Action::WidenPane {
+                steps
+            } else {
+                -steps
+            };
+            app.layout.resize(&app.current_screen, steps);
+        }
+        Action::NextUnread if context != Context::Links && !app.next_unread() => {
+            app.messages.info("No unread posts");
+        }
+        _ => {}
+    }
+    match context {
+        Context::Main => match action {
+            Action::Open => app.current_screen = Screen::FeedMenu,
+            Action::Back => app.current_screen = Screen::Exiting,
+            _ => move_selection(&mut app.view.menu, action, count, rows, page, half_page),
+        },
+        Context::Feed => match action {
+            Action::Open => app.open_post(),
+            Action::Back => app.current_screen = Screen::MainMenu,
+            Action::Star => app.toggle_star(),
+            Action::Tag => app.start_prompt(PromptKind::Tag),
+            Action::MarkRead => app.set_read(true),
+            Action::MarkUnread => app.set_read(false),
+            _ => {
+                if let Some(state) = app.post_state() {
+                    move_selection(state, action, count, rows, page, half_page);
+                }
+            }
+        },
+        Context::Reader => match action {
+            Action::Next => app.view.scroll_down(rows),
+            Action::Prev => app.view.scroll_up(rows),
+            Action::PageDown => app.view.scroll_down(page),
+            Action::PageUp => app.view.scroll_up(page),
+            Action::HalfPageDown => app.view.scroll_down(half_page),
+            Action::HalfPageUp => app.view.scroll_up(half_page),
+            Action::Top => app.view.scroll_to(line(count, 0)),
+            Action::Bottom => app.view.scroll_to(line(count, u16::MAX)),
+            Action::NextPost => app.open_adjacent(times as isize),
+            Action::PrevPost => app.open_adjacent(-(times as isize)),
+            Action::ScrollLeft => (0..times).for_each(|_| app.view.scroll_left()),
+            Action::ScrollRight => (0..times).for_each(|_| app.view.scroll_right()),
+            Action::Back => app.current_screen = Screen::FeedMenu,
+            Action::Star => app.toggle_star(),
+            Action::Tag => app.start_prompt(PromptKind::Tag),
+            Action::MarkRead => app.set_read(true),
+            Action::MarkUnread => app.set_read(false),
+            Action::Links => app.open_link_picker(),
+            Action::Plain => app.view.plain = !app.view.plain,
+            Action::FullArticle => {
+                if let Err(e) = app.toggle_extracted() {
+                    app.messages
+                        .error(format!("Could not get the full article: {e}"));
+                }
+            }
+            _ => {}
+        },
+        Context::Links => match action {
+            Action::Open => app.open_link(),
+            Action::Back => app.picker = None,
+            Action::CopyLink => app.copy_link(),
+            Action::Subscribe => app.subscribe_link(),
+            _ => {
+                if let Some(picker) = &mut app.picker {
+                    picker.number.clear();
+                    move_selection(&mut picker.state, action, count, rows, page, half_page);
+                }
+            }
+        },
+    }
+}
+
+/// Moves a list selection for the movement actions; others are ignored.
+fn move_selection(
+    state: &mut ListState,
+    action: Action,
+    count: Option<usize>,
+    rows: u16,
+    page: u16,
+    half_page: u16,
+) {
+    match action {
+        Action::Next => state.scroll_down_by(rows),
+        Action::Prev => state.scroll_up_by(rows),
+        Action::PageDown => state.scroll_down_by(page),
+        Action::PageUp => state.scroll_up_by(page),
+        Action::HalfPageDown => state.scroll_down_by(half_page),
+        Action::HalfPageUp => state.scroll_up_by(half_page),
+        Action::Top => state.select(Some(count.map_or(0, |n| n.saturating_sub(1)))),
+        Action::Bottom => match count {
+            Some(n) => state.select(Some(n.saturating_sub(1))),
+            None => state.select_last(),
+        },
+        _ => {}
+    }
+}
+
+/// Scrolls with the wheel whatever is under the mouse, selects and opens
+/// list entries and links by clicking, and drags the reader's scrollbar.
+/// Clicking the selected entry opens it.
+fn mouse(app: &mut App, event: MouseEvent) {
+    let areas = app.view.areas;
+    let position = Position::new(event.column, event.row);
+    if app.help || app.log || app.prompt.is_some() {
+        if matches!(event.kind, MouseEventKind::Down(_)) {
+            app.help = false;
+            app.log = false;
+        }
+        return;
+    }
+
+    match event.kind {
+        MouseEventKind::ScrollDown | MouseEventKind::ScrollUp => {
+            let down = event.kind == MouseEventKind::ScrollDown;
+            let step = |state: &mut ListState| {
+                if down {
+                    state.select_next();
+                } else {
+                    state.select_previous();
+                }
+            };
+            if let Some(picker) = &mut app.picker {
+                step(&mut picker.state);
+            } else if areas.reader.contains(position) {
+                if down {
+                    app.view.scroll_down(WHEEL_ROWS);
+                } else {
+                    app.view.scroll_up(WHEEL_ROWS);
+                }
+            } else if areas.posts.contains(position) {
+                if let Some(state) = app.post_state() {
+                    step(state);
+                }
+            } else if areas.menu.contains(position) {
+                step(&mut app.view.menu);
+            }
+        }
+        MouseEventKind::Down(MouseButton::Left) => {
+            if let Some(picker) = &mut app.picker {
+                // Inside the popup's border.
+                let row = event.row.checked_sub(areas.links.y + 1);
+                if let Some(row) = row.filter(|_| areas.links.contains(position)) {
+                    let i = picker.state.offset() + usize::from(row);
+                    if i >= picker.links.len() {
+                        return;
                     }
-                    KeyCode::Enter => {
-                        app.current_screen = Screen::Reader;
+                    picker.number.clear();
+                    if picker.state.selected() == Some(i) {
+                        app.open_link();
+                    } else {
+                        picker.state.select(Some(i));
                     }
-                    _ => {}
-                },
+                }
+            } else if let Some(track) = scrollbar_track(areas.reader)
+                && track.contains(position)
+            {
+                app.view.dragging = true;
+                drag_scrollbar(app, track, event.row);
+            } else if areas.article.contains(position) {
+                let row = usize::from(event.row - areas.article.y) + usize::from(app.view.scroll.0);
+                if let Some(line) = row.checked_sub(usize::from(areas.header_rows)) {
+                    app.open_article_link(line, usize::from(event.column - areas.article.x));
+                }
+            } else if areas.posts.contains(position) {
+                let len = app.posts().len();
+                // The main menu draws the posts from the top, unselected.
+                let listed = !matches!(app.current_screen, Screen::MainMenu);
+                let reading = matches!(app.current_screen, Screen::Reader);
+                let Some(state) = app.post_state() else {
+                    return;
+                };
+                let offset = if listed { state.offset() } else { 0 };
+                let i = offset + usize::from(event.row - areas.posts.y);
+                if i >= len {
+                    return;
+                }
+                // Next to the reader a click opens the post straight away.
+                let open = reading || (listed && state.selected() == Some(i));
+                state.select(Some(i));
+                if open {
+                    app.open_post();
+                } else {
+                    app.current_screen = Screen::FeedMenu;
+                }
+            } else if areas.menu.contains(position) {
+                let i = app.view.menu.offset() + usize::from(event.row - areas.menu.y);
+                if i >= app.feeds.len() + app.folders.len() {
+                    return;
+                }
+                if app.view.menu.selected() == Some(i) {
+                    app.current_screen = Screen::FeedMenu;
+                } else {
+                    app.view.menu.select(Some(i));
+                    app.current_screen = Screen::MainMenu;
+                }
             }
         }
+        MouseEventKind::Drag(MouseButton::Left) if app.view.dragging => {
+            if let Some(track) = scrollbar_track(areas.reader) {
+                drag_scrollbar(app, track, event.row);
+            }
+        }
+        MouseEventKind::Up(_) => app.view.dragging = false,
+        _ => {}
     }
 }
+
+/// The part of the reader's right edge the scrollbar thumb moves along,
+/// between its arrows.
+fn scrollbar_track(reader: Rect) -> Option<Rect> {
+    (reader.height > 2 && reader.width > 0).then(|| Rect {
+        x: reader.right() - 1,
+        y: reader.y + 1,
+        width: 1,
+        height: reader.height - 2,
+    })
+}
+
+/// Scrolls the reader to the share of the article matching `row` on the
+/// scrollbar track.
+fn drag_scrollbar(app: &mut App, track: Rect, row: u16) {
+    let offset = u32::from(row.clamp(track.y, track.bottom() - 1) - track.y);
+    let span = u32::from(track.height.saturating_sub(1)).max(1);
+    let line = offset * u32::from(app.view.scroll_max) / span;
+    app.view.scroll_to(u16::try_from(line).unwrap_or(u16::MAX));
+}
+
+/// Line `count`, counting from one, or `default` without a count.
+fn line(count: Option<usize>, default: u16) -> u16 {
+    count.map_or(default, |n| {
+        u16::try_from(n.saturating_sub(1)).unwrap_or(u16::MAX)
+    })
+}
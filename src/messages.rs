@@ -0,0 +1,70 @@
+use chrono::{DateTime, Local};
+use std::time::{Duration, Instant};
+
+/// How long a message stays in the status bar.
+const SHOWN_FOR: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Level {
+    Info,
+    Error,
+}
+
+pub struct Message {
+    pub time: DateTime<Local>,
+    pub level: Level,
+    pub text: String,
+}
+
+/// Everything reported during the session. The latest message shows in
+/// the status bar for a few seconds; `:messages` lists them all.
+#[derive(Default)]
+pub struct Messages {
+    log: Vec<Message>,
+    /// When the latest message leaves the status bar.
+    until: Option<Instant>,
+}
+
+impl Messages {
+    pub fn info(&mut self, text: impl Into<String>) {
+        self.push(Level::Info, text.into());
+    }
+
+    pub fn error(&mut self, text: impl Into<String>) {
+        self.push(Level::Error, text.into());
+    }
+
+    /// Logs `text`, unless it repeats the latest message, which is shown
+    /// again instead.
+    fn push(&mut self, level: Level, text: String) {
+        self.until = Some(Instant::now() + SHOWN_FOR);
+        if self
+            .log
+            .last()
+            .is_some_and(|m| m.level == level && m.text == text)
+        {
+            return;
+        }
+        self.log.push(Message {
+            time: Local::now(),
+            level,
+            text,
+        });
+    }
+
+    /// The message to show in the status bar, if its time isn't up.
+    pub fn current(&self) -> Option<&Message> {
+        self.log.last().filter(|_| self.remaining().is_some())
+    }
+
+    /// How much longer the current message is shown.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.until
+            .and_then(|until| until.checked_duration_since(Instant::now()))
+            .filter(|d| !d.is_zero())
+    }
+
+    pub fn all(&self) -> &[Message] {
+        &self.log
+    }
+}
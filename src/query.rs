@@ -0,0 +1,275 @@
+use chrono::NaiveDate;
+use regex::{Regex, RegexBuilder};
+use std::error::Error;
+
+use crate::feed::{Metadata, Post};
+
+/// Text matcher for a query value: a case-insensitive substring, or a
+/// regular expression when the value is prefixed with `~`.
+pub enum Pattern {
+    Substring(String),
+    Regex(Regex),
+}
+
+impl Pattern {
+    fn parse(value: &str, regex: bool) -> Result<Pattern, Box<dyn Error>> {
+        if regex {
+            Ok(Pattern::Regex(
+                RegexBuilder::new(value).case_insensitive(true).build()?,
+            ))
+        } else {
+            Ok(Pattern::Substring(value.to_lowercase()))
+        }
+    }
+
+    pub fn is_match(&self, haystack: &str) -> bool {
+        match self {
+            Pattern::Substring(s) => haystack.to_lowercase().contains(s),
+            Pattern::Regex(r) => r.is_match(haystack),
+        }
+    }
+}
+
+pub enum Term {
+    Feed(Pattern),
+    Title(Pattern),
+    Author(Pattern),
+    Tag(Pattern),
+    Content(Pattern),
+    /// Bare word, matched against title and content.
+    Text(Pattern),
+    After(NaiveDate),
+    Before(NaiveDate),
+    Unread,
+    Read,
+    Starred,
+    Not(Box<Term>),
+}
+
+impl Term {
+    pub fn matches(&self, meta: &Metadata, post: &Post) -> bool {
+        let date = post.published.map(|d| d.date_naive());
+        match self {
+            Term::Feed(p) => p.is_match(&meta.title) || p.is_match(&meta.url),
+            Term::Title(p) => p.is_match(&post.title),
+            Term::Author(p) => post.authors.iter().any(|a| p.is_match(a)),
+            Term::Tag(p) => post
+                .categories
+                .iter()
+                .chain(&post.tags)
+                .any(|c| p.is_match(c)),
+            Term::Content(p) => p.is_match(&post.content),
+            Term::Text(p) => p.is_match(&post.title) || p.is_match(&post.content),
+            Term::After(d) => date.is_some_and(|date| date >= *d),
+            Term::Before(d) => date.is_some_and(|date| date < *d),
+            Term::Unread => !post.read,
+            Term::Read => post.read,
+            Term::Starred => post.starred,
+            Term::Not(t) => !t.matches(meta, post),
+        }
+    }
+}
+
+/// A filter over posts, written as space-separated terms that must all match:
+///
+/// `feed:rust unread title:~"release" after:2025-01-01 tag:security`
+///
+/// A term prefixed with `-` is negated. Values may be quoted to include
+/// spaces, and a `~` before the value makes it a regular expression.
+pub struct Query {
+    pub terms: Vec<Term>,
+}
+
+impl Query {
+    pub fn parse(input: &str) -> Result<Query, Box<dyn Error>> {
+        let mut terms = Vec::new();
+        for token in tokenize(input)? {
+            let (negated, token) = match token.text.strip_prefix('-') {
+                Some(rest) if !token.quoted => (true, rest.to_string()),
+                _ => (false, token.text),
+            };
+
+            let term = match token.split_once(':') {
+                Some((key, value)) if !value.is_empty() => {
+                    let (regex, value) = match value.strip_prefix('~') {
+                        Some(v) => (true, unquote(v)),
+                        None => (false, unquote(value)),
+                    };
+                    match key {
+                        "feed" => Term::Feed(Pattern::parse(&value, regex)?),
+                        "title" => Term::Title(Pattern::parse(&value, regex)?),
+                        "author" => Term::Author(Pattern::parse(&value, regex)?),
+                        "tag" => Term::Tag(Pattern::parse(&value, regex)?),
+                        "content" => Term::Content(Pattern::parse(&value, regex)?),
+                        "after" => Term::After(parse_date(&value)?),
+                        "before" => Term::Before(parse_date(&value)?),
+                        "is" => flag(&value)?,
+                        _ => return Err(format!("Unknown query key: {key}").into()),
+                    }
+                }
+                _ => match flag(&token) {
+                    Ok(term) => term,
+                    Err(_) => Term::Text(Pattern::parse(&unquote(&token), false)?),
+                },
+            };
+
+            terms.push(if negated {
+                Term::Not(Box::new(term))
+            } else {
+                term
+            });
+        }
+
+        Ok(Query { terms })
+    }
+
+    pub fn matches(&self, meta: &Metadata, post: &Post) -> bool {
+        self.terms.iter().all(|t| t.matches(meta, post))
+    }
+}
+
+struct Token {
+    text: String,
+    quoted: bool,
+}
+
+/// Splits on whitespace, keeping double-quoted sections (quotes included)
+/// together so values like `title:~"release notes"` stay one token.
+fn tokenize(input: &str) -> Result<Vec<Token>, Box<dyn Error>> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+
+    for c in input.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                current.push(c);
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if !current.is_empty() {
+                    tokens.push(Token {
+                        quoted: current.starts_with('"'),
+                        text: std::mem::take(&mut current),
+                    });
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if in_quotes {
+        return Err("Unterminated quote in query".into());
+    }
+    if !current.is_empty() {
+        tokens.push(Token {
+            quoted: current.starts_with('"'),
+            text: current,
+        });
+    }
+
+    Ok(tokens)
+}
+
+fn unquote(value: &str) -> String {
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value)
+        .to_string()
+}
+
+fn parse_date(value: &str) -> Result<NaiveDate, Box<dyn Error>> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|e| format!("Invalid date '{value}': {e}").into())
+}
+
+fn flag(value: &str) -> Result<Term, Box<dyn Error>> {
+    match value {
+        "unread" => Ok(Term::Unread),
+        "read" => Ok(Term::Read),
+        "starred" => Ok(Term::Starred),
+        _ => Err(format!("Unknown flag: {value}").into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DateTime;
+
+    fn meta() -> Metadata {
+        Metadata {
+            title: "This Week in Rust".to_string(),
+            url: "https://this-week-in-rust.org/".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn post() -> Post {
+        Post {
+            title: "Rust 1.80 release notes".to_string(),
+            content: "<p>Lazy statics are stable.</p>".to_string(),
+            authors: vec!["Ferris".to_string()],
+            categories: vec!["release".to_string()],
+            tags: vec!["later".to_string()],
+            published: Some(DateTime::parse_from_rfc3339("2024-07-25T12:00:00+00:00").unwrap()),
+            ..Default::default()
+        }
+    }
+
+    fn matches(query: &str) -> bool {
+        Query::parse(query).unwrap().matches(&meta(), &post())
+    }
+
+    #[test]
+    fn every_term_must_match() {
+        assert!(matches("feed:rust title:release author:ferris unread"));
+        assert!(!matches("feed:rust title:release starred"));
+        assert!(matches(""));
+    }
+
+    #[test]
+    fn bare_words_match_title_or_content() {
+        assert!(matches("notes"));
+        assert!(matches("LAZY"));
+        assert!(!matches("python"));
+    }
+
+    #[test]
+    fn negates_terms() {
+        assert!(matches("-starred"));
+        assert!(matches("-is:read"));
+        assert!(!matches("-feed:rust"));
+    }
+
+    #[test]
+    fn tags_match_categories_and_user_tags() {
+        assert!(matches("tag:release"));
+        assert!(matches("tag:later"));
+        assert!(!matches("tag:security"));
+    }
+
+    #[test]
+    fn quoted_and_regex_values() {
+        assert!(matches(r#"title:"release notes""#));
+        assert!(matches(r#"title:~"^rust \d+\.\d+""#));
+        assert!(!matches("title:~^notes"));
+        assert!(matches(r#""release notes""#));
+    }
+
+    #[test]
+    fn date_ranges() {
+        assert!(matches("after:2024-07-25 before:2024-07-26"));
+        assert!(!matches("after:2024-07-26"));
+        assert!(!matches("before:2024-07-25"));
+    }
+
+    #[test]
+    fn rejects_malformed_queries() {
+        assert!(Query::parse(r#"title:"release"#).is_err());
+        assert!(Query::parse("colour:red").is_err());
+        assert!(Query::parse("after:yesterday").is_err());
+        assert!(Query::parse("is:pinned").is_err());
+        assert!(Query::parse("title:~(").is_err());
+    }
+}
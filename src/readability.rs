@@ -0,0 +1,222 @@
+use markup5ever_rcdom::{Handle, Node, SerializableHandle};
+use regex::Regex;
+use std::{collections::HashMap, rc::Rc, sync::LazyLock};
+
+use crate::{
+    sanitize,
+    styling::{self, attr, tag_name, text_content},
+};
+
+/// Class and id words that mark the main content of a page.
+static POSITIVE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)article|body|content|entry|main|page|post|text|blog|story").unwrap()
+});
+/// Class and id words that mark page furniture around it.
+static NEGATIVE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)comment|meta|footer|footnote|sidebar|sponsor|share|social|nav|menu|banner|ad-|promo|related|popup|cookie|subscribe|newsletter",
+    )
+    .unwrap()
+});
+
+/// Elements that never hold the article.
+const FURNITURE: &[&str] = &["nav", "header", "footer", "aside"];
+
+/// Shortest paragraph that counts towards its container's score.
+const MIN_PARAGRAPH: usize = 25;
+/// Least text an extraction must have to be worth showing.
+const MIN_ARTICLE: usize = 250;
+
+/// Finds the main content of a web page, Readability-style: paragraphs
+/// score their parent and grandparent by length and commas, containers are
+/// weighted by tag and class, and the best-scoring container, discounted by
+/// how much of it is links, is the article. Returns its inner HTML, or
+/// `None` when nothing on the page looks like an article.
+pub fn extract(html: &[u8]) -> Option<String> {
+    let dom = styling::parse(html);
+    sanitize::sanitize(&dom.document);
+    strip_furniture(&dom.document);
+
+    let mut scores: HashMap<*const Node, (Handle, f64)> = HashMap::new();
+    score(&dom.document, &mut Vec::new(), &mut scores);
+
+    let (best, _) = scores
+        .into_values()
+        .map(|(node, score)| {
+            let weighted = score * (1.0 - link_density(&node));
+            (node, weighted)
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))?;
+
+    if text_content(&best).trim().len() < MIN_ARTICLE {
+        return None;
+    }
+
+    let mut bytes = Vec::new();
+    html5ever::serialize(
+        &mut bytes,
+        &SerializableHandle::from(best),
+        Default::default(),
+    )
+    .ok()?;
+    String::from_utf8(bytes).ok()
+}
+
+/// Removes elements that are page furniture by tag, or by a class or id
+/// that looks negative and not positive.
+fn strip_furniture(node: &Handle) {
+    node.children.borrow_mut().retain(|child| {
+        let Some(tag) = tag_name(child) else {
+            return true;
+        };
+        let names = format!(
+            "{} {}",
+            attr(child, "class").unwrap_or_default(),
+            attr(child, "id").unwrap_or_default()
+        );
+        let unlikely = NEGATIVE.is_match(&names) && !POSITIVE.is_match(&names);
+        !(FURNITURE.contains(&tag.as_str()) || (unlikely && tag != "body" && tag != "html"))
+    });
+    for child in node.children.borrow().iter() {
+        strip_furniture(child);
+    }
+}
+
+/// Walks the tree with `ancestors` as the path to `node`, crediting each
+/// paragraph's score to its parent in full and its grandparent by half.
+fn score(
+    node: &Handle,
+    ancestors: &mut Vec<Handle>,
+    scores: &mut HashMap<*const Node, (Handle, f64)>,
+) {
+    if matches!(tag_name(node).as_deref(), Some("p" | "pre" | "td")) {
+        let text = text_content(node);
+        let text = text.trim();
+        if text.len() >= MIN_PARAGRAPH {
+            let points = 1.0 + text.matches(',').count() as f64 + (text.len() / 100).min(3) as f64;
+            for (depth, ancestor) in ancestors.iter().rev().take(2).enumerate() {
+                let entry = scores
+                    .entry(Rc::as_ptr(ancestor))
+                    .or_insert_with(|| (ancestor.clone(), base_score(ancestor)));
+                entry.1 += if depth == 0 { points } else { points / 2.0 };
+            }
+        }
+    }
+
+    ancestors.push(node.clone());
+    for child in node.children.borrow().iter() {
+        score(child, ancestors, scores);
+    }
+    ancestors.pop();
+}
+
+/// Starting score of a container, from its tag and its class and id.
+fn base_score(node: &Handle) -> f64 {
+    let tag = match tag_name(node).as_deref() {
+        Some("div" | "article" | "main" | "section") => 5.0,
+        Some("pre" | "td" | "blockquote") => 3.0,
+        Some("ol" | "ul" | "dl" | "dd" | "dt" | "li" | "form") => -3.0,
+        Some("h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "th") => -5.0,
+        _ => 0.0,
+    };
+    let names = format!(
+        "{} {}",
+        attr(node, "class").unwrap_or_default(),
+        attr(node, "id").unwrap_or_default()
+    );
+    let mut weight = 0.0;
+    if POSITIVE.is_match(&names) {
+        weight += 25.0;
+    }
+    if NEGATIVE.is_match(&names) {
+        weight -= 25.0;
+    }
+    tag + weight
+}
+
+/// Share of a node's text that is inside links.
+fn link_density(node: &Handle) -> f64 {
+    fn link_text(node: &Handle) -> usize {
+        if tag_name(node).as_deref() == Some("a") {
+            return text_content(node).trim().len();
+        }
+        node.children.borrow().iter().map(link_text).sum()
+    }
+
+    let total = text_content(node).trim().len();
+    if total == 0 {
+        return 1.0;
+    }
+    link_text(node) as f64 / total as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::styling::parse;
+
+    const PARAGRAPH: &str = "<p>The committee met on Tuesday, and after a long debate, \
+                             it agreed to publish the report, with some changes.</p>";
+
+    fn page(body: &str) -> String {
+        format!("<html><head><title>Page</title></head><body>{body}</body></html>")
+    }
+
+    #[test]
+    fn extracts_the_article_without_the_furniture() {
+        let html = page(&format!(
+            "<nav><p>Home, News, Sport, Weather, and everything else you might want</p></nav>\
+             <div class=\"story-body\">{}</div>\
+             <div class=\"comments\">{PARAGRAPH}</div>\
+             <footer>Copyright, all rights reserved, and so on and so forth</footer>",
+            PARAGRAPH.repeat(4)
+        ));
+        let article = extract(html.as_bytes()).unwrap();
+
+        assert_eq!(article, PARAGRAPH.repeat(4));
+    }
+
+    #[test]
+    fn prefers_text_over_link_lists() {
+        let links: String = (0..8)
+            .map(|i| {
+                format!(
+                    "<p><a href=\"/{i}\">A related story, with a long headline, number {i}</a></p>"
+                )
+            })
+            .collect();
+        let html = page(&format!(
+            "<div id=\"links\">{links}</div><div id=\"text\">{}</div>",
+            PARAGRAPH.repeat(3)
+        ));
+        let article = extract(html.as_bytes()).unwrap();
+
+        assert_eq!(article, PARAGRAPH.repeat(3));
+    }
+
+    #[test]
+    fn gives_up_on_pages_without_an_article() {
+        assert_eq!(extract(page(PARAGRAPH).as_bytes()), None);
+        assert_eq!(
+            extract(page("<ul><li>a</li><li>b</li></ul>").as_bytes()),
+            None
+        );
+    }
+
+    #[test]
+    fn scores_containers_by_tag_and_name() {
+        let dom =
+            parse(b"<div class=\"post-content\"></div><div class=\"sidebar\"></div><h2></h2>");
+        let mut scores = Vec::new();
+        fn walk(node: &Handle, scores: &mut Vec<f64>) {
+            if matches!(tag_name(node).as_deref(), Some("div" | "h2")) {
+                scores.push(base_score(node));
+            }
+            for child in node.children.borrow().iter() {
+                walk(child, scores);
+            }
+        }
+        walk(&dom.document, &mut scores);
+        assert_eq!(scores, [30.0, -20.0, -5.0]);
+    }
+}
@@ -0,0 +1,176 @@
+use chrono::{Duration, Utc};
+use serde::Deserialize;
+
+use crate::feed::RussFeed;
+
+/// How many posts a feed keeps. Set globally under `[retention]` and
+/// overridden per feed; a limit of `0` means no limit.
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Retention {
+    pub max_posts: Option<usize>,
+    pub max_age_days: Option<i64>,
+    pub keep_unread: Option<bool>,
+}
+
+impl Retention {
+    /// This policy with any fields set in `feed` taking precedence.
+    pub fn with_override(&self, feed: &Retention) -> Retention {
+        Retention {
+            max_posts: feed.max_posts.or(self.max_posts),
+            max_age_days: feed.max_age_days.or(self.max_age_days),
+            keep_unread: feed.keep_unread.or(self.keep_unread),
+        }
+    }
+
+    /// Indices of the posts in `feed` this policy would remove. Starred
+    /// posts, and unread ones when `keep_unread` is set, are always kept and
+    /// don't count towards `max_posts`. Posts are stored newest first, so the
+    /// limit keeps the top of the list.
+    pub fn expired(&self, feed: &RussFeed) -> Vec<usize> {
+        let max_posts = self.max_posts.filter(|&n| n > 0).unwrap_or(usize::MAX);
+        let cutoff = self
+            .max_age_days
+            .filter(|&d| d > 0)
+            .map(|d| Utc::now() - Duration::days(d));
+        let keep_unread = self.keep_unread.unwrap_or(false);
+
+        let mut kept = 0;
+        let mut expired = Vec::new();
+        for (i, post) in feed.posts.iter().enumerate() {
+            let exempt = post.starred || (keep_unread && !post.read);
+            let too_old = match (cutoff, post.published) {
+                (Some(cutoff), Some(published)) => published < cutoff,
+                _ => false,
+            };
+
+            if exempt {
+                continue;
+            }
+            if too_old || kept >= max_posts {
+                expired.push(i);
+            } else {
+                kept += 1;
+            }
+        }
+        expired
+    }
+}
+
+pub struct Pruned {
+    pub posts: usize,
+    pub bytes: usize,
+}
+
+/// Removes the posts `policy` marks as expired, remembering their keys so a
+/// later refresh does not bring them back. With `dry_run` the feed is left
+/// untouched and only the counts are returned.
+pub fn prune(feed: &mut RussFeed, policy: &Retention, dry_run: bool) -> Pruned {
+    let expired = policy.expired(feed);
+    let bytes = expired
+        .iter()
+        .map(|&i| serde_json::to_string(&feed.posts[i]).map_or(0, |s| s.len()))
+        .sum();
+    let pruned = Pruned {
+        posts: expired.len(),
+        bytes,
+    };
+
+    if !dry_run {
+        for &i in expired.iter().rev() {
+            let post = feed.posts.remove(i);
+            feed.pruned.push(post.key().to_string());
+        }
+    }
+
+    pruned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feed::{Metadata, Post};
+
+    fn post(id: &str, days_old: i64) -> Post {
+        Post {
+            id: id.to_string(),
+            published: Some(
+                (Utc::now() - Duration::days(days_old) + Duration::hours(1)).fixed_offset(),
+            ),
+            ..Default::default()
+        }
+    }
+
+    /// Five posts, newest first, a day apart. Each is an hour short of its
+    /// age in days so the cutoff doesn't race the clock.
+    fn feed() -> RussFeed {
+        RussFeed {
+            meta: Metadata::default(),
+            posts: (0..5).map(|i| post(&i.to_string(), i)).collect(),
+            pruned: Vec::new(),
+        }
+    }
+
+    fn policy(max_posts: Option<usize>, max_age_days: Option<i64>) -> Retention {
+        Retention {
+            max_posts,
+            max_age_days,
+            keep_unread: None,
+        }
+    }
+
+    #[test]
+    fn keeps_the_newest_posts() {
+        assert_eq!(policy(Some(3), None).expired(&feed()), [3, 4]);
+        assert!(policy(Some(0), None).expired(&feed()).is_empty());
+        assert!(policy(None, None).expired(&feed()).is_empty());
+    }
+
+    #[test]
+    fn expires_old_posts() {
+        assert_eq!(policy(None, Some(2)).expired(&feed()), [3, 4]);
+    }
+
+    #[test]
+    fn exempt_posts_are_kept_and_not_counted() {
+        let mut feed = feed();
+        for post in &mut feed.posts {
+            post.read = true;
+        }
+        feed.posts[0].starred = true;
+        feed.posts[4].read = false;
+
+        assert_eq!(policy(Some(2), None).expired(&feed), [3, 4]);
+
+        let keep_unread = Retention {
+            keep_unread: Some(true),
+            ..policy(Some(2), None)
+        };
+        assert_eq!(keep_unread.expired(&feed), [3]);
+    }
+
+    #[test]
+    fn feed_overrides_global_policy() {
+        let global = Retention {
+            keep_unread: Some(true),
+            ..policy(Some(100), Some(30))
+        };
+        let merged = global.with_override(&policy(Some(10), None));
+        assert_eq!(merged.max_posts, Some(10));
+        assert_eq!(merged.max_age_days, Some(30));
+        assert_eq!(merged.keep_unread, Some(true));
+    }
+
+    #[test]
+    fn prune_remembers_removed_keys() {
+        let mut feed = feed();
+        let pruned = prune(&mut feed, &policy(Some(3), None), true);
+        assert_eq!(pruned.posts, 2);
+        assert!(pruned.bytes > 0);
+        assert_eq!(feed.posts.len(), 5);
+
+        prune(&mut feed, &policy(Some(3), None), false);
+        assert_eq!(feed.posts.len(), 3);
+        assert_eq!(feed.pruned, ["4", "3"]);
+    }
+}
@@ -0,0 +1,287 @@
+use ratatui::style::Color;
+use regex::{Regex, RegexBuilder};
+use serde::Deserialize;
+use std::{error::Error, str::FromStr};
+
+use crate::feed::{Metadata, Post};
+
+/// A rule as written in the config:
+///
+/// ```toml
+/// [[rules]]
+/// name = "no football"
+/// feed = "guardian"
+/// title = "football|premier league"
+/// actions = ["read", "hide"]
+/// ```
+#[derive(Deserialize)]
+pub struct RuleConfig {
+    pub name: String,
+    pub feed: Option<String>,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub category: Option<String>,
+    pub content: Option<String>,
+    pub actions: Vec<String>,
+}
+
+pub enum Action {
+    MarkRead,
+    Hide,
+    Star,
+    Tag(String),
+    /// Colour name, checked when the rule is loaded.
+    Highlight(String),
+}
+
+impl FromStr for Action {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Action, Self::Err> {
+        match s.split_once(':') {
+            Some(("tag", tag)) => Ok(Action::Tag(tag.to_string())),
+            Some(("highlight", color)) => {
+                Color::from_str(color).map_err(|_| format!("Invalid colour: {color}"))?;
+                Ok(Action::Highlight(color.to_string()))
+            }
+            _ => match s {
+                "read" => Ok(Action::MarkRead),
+                "hide" => Ok(Action::Hide),
+                "star" => Ok(Action::Star),
+                _ => Err(format!("Unknown action: {s}").into()),
+            },
+        }
+    }
+}
+
+/// Compiled rule. Every pattern that is set must match (case-insensitively)
+/// for the actions to run.
+pub struct Rule {
+    pub name: String,
+    feed: Option<Regex>,
+    title: Option<Regex>,
+    author: Option<Regex>,
+    category: Option<Regex>,
+    content: Option<Regex>,
+    pub actions: Vec<Action>,
+}
+
+impl Rule {
+    pub fn from_config(config: &RuleConfig) -> Result<Rule, Box<dyn Error>> {
+        fn compile(pattern: &Option<String>) -> Result<Option<Regex>, Box<dyn Error>> {
+            match pattern {
+                Some(p) => Ok(Some(RegexBuilder::new(p).case_insensitive(true).build()?)),
+                None => Ok(None),
+            }
+        }
+
+        let rule = Rule {
+            name: config.name.clone(),
+            feed: compile(&config.feed)?,
+            title: compile(&config.title)?,
+            author: compile(&config.author)?,
+            category: compile(&config.category)?,
+            content: compile(&config.content)?,
+            actions: config
+                .actions
+                .iter()
+                .map(|a| a.parse())
+                .collect::<Result<_, _>>()?,
+        };
+
+        if [
+            &rule.feed,
+            &rule.title,
+            &rule.author,
+            &rule.category,
+            &rule.content,
+        ]
+        .iter()
+        .all(|p| p.is_none())
+        {
+            return Err(format!("Rule '{}' has nothing to match on", rule.name).into());
+        }
+
+        Ok(rule)
+    }
+
+    pub fn matches(&self, meta: &Metadata, post: &Post) -> bool {
+        let feed = self
+            .feed
+            .as_ref()
+            .is_none_or(|r| r.is_match(&meta.title) || r.is_match(&meta.source));
+        let title = self.title.as_ref().is_none_or(|r| r.is_match(&post.title));
+        let author = self
+            .author
+            .as_ref()
+            .is_none_or(|r| post.authors.iter().any(|a| r.is_match(a)));
+        let category = self
+            .category
+            .as_ref()
+            .is_none_or(|r| post.categories.iter().any(|c| r.is_match(c)));
+        let content = self
+            .content
+            .as_ref()
+            .is_none_or(|r| r.is_match(&post.content));
+
+        feed && title && author && category && content
+    }
+
+    /// Carries out the rule's actions on `post`. `state_changed` is left
+    /// alone: every machine runs the same rules, so their changes must not
+    /// win over the user's when replicas are merged. `apply_rules` makes an
+    /// exception for synced feeds.
+    pub fn apply(&self, post: &mut Post) {
+        for action in &self.actions {
+            match action {
+                Action::MarkRead => post.read = true,
+                Action::Hide => post.hidden = true,
+                Action::Star => post.starred = true,
+                Action::Tag(tag) => {
+                    if !post.tags.contains(tag) {
+                        post.tags.push(tag.clone());
+                    }
+                }
+                Action::Highlight(color) => post.highlight = Some(color.clone()),
+            }
+        }
+    }
+}
+
+/// Runs every matching rule on each post, returning how many posts matched
+/// at least one rule. On a feed synced with a server, a post whose read or
+/// starred flag changes is touched so the next sync pushes it; otherwise the
+/// server's state would undo it at the next pull.
+pub fn apply_rules<'a>(
+    rules: &[Rule],
+    meta: &Metadata,
+    posts: impl Iterator<Item = &'a mut Post>,
+) -> usize {
+    let mut hits = 0;
+    for post in posts {
+        let mut hit = false;
+        let flags = (post.read, post.starred);
+        for rule in rules {
+            if rule.matches(meta, post) {
+                rule.apply(post);
+                hit = true;
+            }
+        }
+        if meta.remote.is_some() && (post.read, post.starred) != flags {
+            post.touch();
+        }
+        hits += usize::from(hit);
+    }
+    hits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(title: Option<&str>, actions: &[&str]) -> RuleConfig {
+        RuleConfig {
+            name: "test".to_string(),
+            feed: Some("guardian".to_string()),
+            title: title.map(str::to_string),
+            author: None,
+            category: None,
+            content: None,
+            actions: actions.iter().map(|a| a.to_string()).collect(),
+        }
+    }
+
+    fn meta() -> Metadata {
+        Metadata {
+            title: "The Guardian".to_string(),
+            source: "https://www.theguardian.com/uk/rss".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn post(title: &str) -> Post {
+        Post {
+            title: title.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn every_pattern_must_match() {
+        let rule = Rule::from_config(&config(Some("football|premier league"), &["read"])).unwrap();
+        assert!(rule.matches(&meta(), &post("Premier League results")));
+        assert!(!rule.matches(&meta(), &post("Election results")));
+
+        let other = Metadata {
+            title: "BBC".to_string(),
+            ..Default::default()
+        };
+        assert!(!rule.matches(&other, &post("Premier League results")));
+    }
+
+    #[test]
+    fn feed_pattern_matches_source() {
+        let mut config = config(None, &["read"]);
+        config.feed = Some("theguardian\\.com".to_string());
+        let rule = Rule::from_config(&config).unwrap();
+        assert!(rule.matches(&meta(), &post("Anything")));
+    }
+
+    #[test]
+    fn applies_actions() {
+        let rule = Rule::from_config(&config(
+            None,
+            &["read", "hide", "star", "tag:sport", "highlight:yellow"],
+        ))
+        .unwrap();
+        let mut post = post("Football");
+        post.tags.push("sport".to_string());
+        rule.apply(&mut post);
+
+        assert!(post.read && post.hidden && post.starred);
+        assert_eq!(post.tags, ["sport"]);
+        assert_eq!(post.highlight.as_deref(), Some("yellow"));
+        assert!(post.state_changed.is_none());
+    }
+
+    #[test]
+    fn counts_posts_matching_any_rule() {
+        let rules = [
+            Rule::from_config(&config(Some("football"), &["read"])).unwrap(),
+            Rule::from_config(&config(Some("league"), &["star"])).unwrap(),
+        ];
+        let mut posts = [post("Football league"), post("Football"), post("Weather")];
+
+        assert_eq!(apply_rules(&rules, &meta(), posts.iter_mut()), 2);
+        assert!(posts[0].read && posts[0].starred);
+        assert!(posts[1].read && !posts[1].starred);
+        assert!(!posts[2].read);
+        assert!(posts.iter().all(|p| p.state_changed.is_none()));
+    }
+
+    #[test]
+    fn touches_posts_on_synced_feeds() {
+        let rules = [Rule::from_config(&config(None, &["read", "tag:news"])).unwrap()];
+        let meta = Metadata {
+            remote: Some("3".to_string()),
+            ..meta()
+        };
+        let mut unread = post("Unread");
+        let mut read = post("Read");
+        read.read = true;
+
+        apply_rules(&rules, &meta, [&mut unread, &mut read].into_iter());
+        assert!(unread.state_changed.is_some());
+        assert!(read.state_changed.is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        let mut empty = config(None, &["read"]);
+        empty.feed = None;
+        assert!(Rule::from_config(&empty).is_err());
+        assert!(Rule::from_config(&config(None, &["delete"])).is_err());
+        assert!(Rule::from_config(&config(None, &["highlight:octarine"])).is_err());
+        assert!(Rule::from_config(&config(Some("("), &["read"])).is_err());
+    }
+}
@@ -0,0 +1,189 @@
+use markup5ever_rcdom::{Handle, NodeData};
+use reqwest::Url;
+
+use crate::styling::{attr, tag_name, text_content};
+
+/// Elements dropped with everything inside them: scripts, embeds and form
+/// controls have nothing to show in a terminal.
+const REMOVED: &[&str] = &[
+    "script", "noscript", "iframe", "svg", "math", "object", "embed", "template", "canvas", "link",
+    "meta", "button", "input", "select", "textarea",
+];
+
+/// Image hosts and paths that only serve tracking pixels.
+const TRACKERS: &[&str] = &[
+    "feeds.feedburner.com/~r/",
+    "feedproxy.google.com/~r/",
+    "pixel.wp.com",
+    "stats.wp.com",
+    "stats.wordpress.com",
+    "doubleclick.net",
+    "google-analytics.com",
+    "pixel.quantserve.com",
+    "feedblitz.com/_/",
+    "list-manage.com/track",
+    "/wf/open",
+];
+
+/// Query parameters that only identify where a click came from.
+const TRACKING_PARAMS: &[&str] = &[
+    "fbclid", "gclid", "dclid", "msclkid", "mc_cid", "mc_eid", "igshid", "yclid", "_hsenc",
+    "_hsmi", "mkt_tok", "ref_src",
+];
+
+/// Removes what should not be rendered from a parsed document: the
+/// elements in `REMOVED`, hidden elements and tracking pixels.
+pub fn sanitize(node: &Handle) {
+    node.children.borrow_mut().retain(|child| !unwanted(child));
+    for child in node.children.borrow().iter() {
+        sanitize(child);
+    }
+}
+
+fn unwanted(node: &Handle) -> bool {
+    let Some(tag) = tag_name(node) else {
+        return false;
+    };
+    if REMOVED.contains(&tag.as_str()) {
+        return true;
+    }
+
+    let hidden = attr(node, "hidden").is_some()
+        || attr(node, "aria-hidden").is_some_and(|v| v == "true")
+        || attr(node, "style").is_some_and(|style| {
+            let style: String = style.split_whitespace().collect();
+            style.contains("display:none") || style.contains("visibility:hidden")
+        });
+    hidden || (tag == "img" && is_tracking_pixel(node))
+}
+
+fn is_tracking_pixel(img: &Handle) -> bool {
+    let tiny = ["width", "height"].iter().any(|name| {
+        attr(img, name)
+            .and_then(|v| v.trim_end_matches("px").parse::<u32>().ok())
+            .is_some_and(|v| v <= 1)
+    });
+    let src = attr(img, "src").unwrap_or_default();
+    tiny || TRACKERS.iter().any(|tracker| src.contains(tracker))
+}
+
+/// `url` without `utm_*` and other click-tracking query parameters.
+pub fn strip_tracking(url: &str) -> String {
+    let Ok(mut parsed) = Url::parse(url) else {
+        return url.to_string();
+    };
+    if parsed.query().is_none() {
+        return url.to_string();
+    }
+
+    let pairs = parsed.query_pairs().count();
+    let kept: Vec<(String, String)> = parsed
+        .query_pairs()
+        .filter(|(name, _)| !name.starts_with("utm_") && !TRACKING_PARAMS.contains(&name.as_ref()))
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect();
+    if kept.len() == pairs {
+        return url.to_string();
+    }
+    if kept.is_empty() {
+        parsed.set_query(None);
+    } else {
+        parsed.query_pairs_mut().clear().extend_pairs(kept);
+    }
+    parsed.to_string()
+}
+
+/// Whether the document holds no markup, only text that itself looks like
+/// HTML: content that was escaped twice on its way into the feed.
+pub fn is_escaped(document: &Handle) -> bool {
+    fn has_markup(node: &Handle) -> bool {
+        node.children
+            .borrow()
+            .iter()
+            .any(|child| match &child.data {
+                NodeData::Element { .. } => {
+                    !matches!(tag_name(child).as_deref(), Some("html" | "head" | "body"))
+                        || has_markup(child)
+                }
+                _ => false,
+            })
+    }
+
+    let text = text_content(document);
+    !has_markup(document) && (text.contains("</") || text.contains("/>"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::styling::parse;
+
+    /// Tag names left in `html` after sanitizing, in document order.
+    fn tags_after_sanitize(html: &str) -> Vec<String> {
+        fn collect(node: &Handle, tags: &mut Vec<String>) {
+            if let Some(tag) = tag_name(node)
+                && !matches!(tag.as_str(), "html" | "head" | "body")
+            {
+                tags.push(tag);
+            }
+            for child in node.children.borrow().iter() {
+                collect(child, tags);
+            }
+        }
+        let dom = parse(html.as_bytes());
+        sanitize(&dom.document);
+        let mut tags = Vec::new();
+        collect(&dom.document, &mut tags);
+        tags
+    }
+
+    #[test]
+    fn removes_scripts_embeds_and_hidden_elements() {
+        let html = r#"<p>kept</p><script>alert(1)</script><iframe src="x"></iframe>
+            <div hidden>a</div><span aria-hidden="true">b</span>
+            <div style="display: none">c</div><em style="visibility:hidden">d</em>"#;
+        assert_eq!(tags_after_sanitize(html), ["p"]);
+    }
+
+    #[test]
+    fn removes_tracking_pixels() {
+        let html = r#"<img src="https://example.com/a.png" width="1" height="1">
+            <img src="https://example.com/b.gif" height="1px">
+            <img src="https://pixel.wp.com/g.gif">
+            <img src="https://feeds.feedburner.com/~r/blog/~4/abc">
+            <img src="https://example.com/photo.jpg" width="640">"#;
+        assert_eq!(tags_after_sanitize(html), ["img"]);
+    }
+
+    #[test]
+    fn strips_tracking_parameters_only() {
+        assert_eq!(
+            strip_tracking("https://example.com/a?id=3&utm_source=rss&utm_medium=feed&fbclid=x"),
+            "https://example.com/a?id=3"
+        );
+        assert_eq!(
+            strip_tracking("https://example.com/a?utm_campaign=c"),
+            "https://example.com/a"
+        );
+        assert_eq!(
+            strip_tracking("https://example.com/a?page=2&sort=new"),
+            "https://example.com/a?page=2&sort=new"
+        );
+        assert_eq!(
+            strip_tracking("not a url?utm_source=x"),
+            "not a url?utm_source=x"
+        );
+    }
+
+    #[test]
+    fn detects_double_escaped_content() {
+        let escaped = parse(b"&lt;p&gt;Hello &lt;b&gt;world&lt;/b&gt;&lt;/p&gt;");
+        assert!(is_escaped(&escaped.document));
+
+        let markup = parse(b"<p>Hello <b>world</b></p>");
+        assert!(!is_escaped(&markup.document));
+
+        let prose = parse(b"Use a &lt; b for comparisons");
+        assert!(!is_escaped(&prose.document));
+    }
+}
@@ -0,0 +1,114 @@
+use ratatui::widgets::ListState;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, error::Error, fs, path::Path};
+
+use crate::{store, ui::Areas};
+
+pub const SESSION_VERSION: u32 = 1;
+
+/// Columns code blocks move per horizontal scroll.
+const HSCROLL_STEP: u16 = 8;
+
+/// Presentation state kept out of the feed data: list selections and the
+/// reader's scroll offset, as `(line, column)`; the column only moves code
+/// blocks. Post lists are keyed by `App::entry_key`.
+pub struct View {
+    pub menu: ListState,
+    pub lists: HashMap<String, ListState>,
+    pub scroll: (u16, u16),
+    /// Show articles as plain text.
+    pub plain: bool,
+    /// Show the feed's content even when an extracted article exists.
+    pub original: bool,
+    /// Rows of the list or article last drawn, for paging.
+    pub page_rows: u16,
+    /// Furthest the reader scrolls: the article's last row at the bottom.
+    pub scroll_max: u16,
+    /// Furthest code blocks scroll right: the widest line's end in view.
+    pub hscroll_max: u16,
+    pub areas: Areas,
+    /// The reader's scrollbar is being dragged.
+    pub dragging: bool,
+}
+
+impl Default for View {
+    fn default() -> View {
+        View {
+            menu: ListState::default().with_selected(Some(0)),
+            lists: HashMap::new(),
+            scroll: (0, 0),
+            plain: false,
+            original: false,
+            page_rows: 1,
+            scroll_max: 0,
+            hscroll_max: 0,
+            areas: Areas::default(),
+            dragging: false,
+        }
+    }
+}
+
+impl View {
+    pub fn list(&mut self, key: &str) -> &mut ListState {
+        self.lists
+            .entry(key.to_string())
+            .or_insert_with(|| ListState::default().with_selected(Some(0)))
+    }
+
+    pub fn scroll_up(&mut self, rows: u16) {
+        self.scroll.0 = self.scroll.0.saturating_sub(rows);
+    }
+
+    pub fn scroll_down(&mut self, rows: u16) {
+        self.scroll_to(self.scroll.0.saturating_add(rows));
+    }
+
+    /// Scrolls the reader to `line`, or as far as it goes.
+    pub fn scroll_to(&mut self, line: u16) {
+        self.scroll.0 = line.min(self.scroll_max);
+    }
+
+    pub fn scroll_left(&mut self) {
+        self.scroll.1 = self.scroll.1.saturating_sub(HSCROLL_STEP);
+    }
+
+    pub fn scroll_right(&mut self) {
+        self.scroll.1 = self
+            .scroll
+            .1
+            .saturating_add(HSCROLL_STEP)
+            .min(self.hscroll_max);
+    }
+}
+
+/// What `session.json` remembers between runs. Selections are stored by
+/// feed id and post key rather than by index, so they survive refreshes.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Session {
+    pub version: u32,
+    /// Selected main menu entry.
+    pub entry: Option<String>,
+    /// Selected post key for each main menu entry.
+    pub posts: HashMap<String, String>,
+    /// Whether the reader was open, and where it was scrolled to.
+    pub reading: bool,
+    pub scroll: (u16, u16),
+}
+
+impl Session {
+    /// Reads the session file. A missing or unreadable file is not an
+    /// error; russ just starts at the top.
+    pub fn load(path: &Path) -> Session {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|s| serde_json::from_str::<Session>(&s).ok())
+            .filter(|s| s.version <= SESSION_VERSION)
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        store::write_atomic(path, serde_json::to_string(self)?.as_bytes())?;
+        Ok(())
+    }
+}
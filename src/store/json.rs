@@ -0,0 +1,166 @@
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    app::Index,
+    feed::{Post, RussFeed},
+    store::{Paths, Store},
+};
+
+/// Version written to `index.json` and feed files. Files from before
+/// versioning read as version 0, and their leftover UI fields are ignored.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Versioned<T> {
+    #[serde(default)]
+    version: u32,
+    #[serde(flatten)]
+    data: T,
+}
+
+pub fn to_string<T: Serialize>(data: &T) -> Result<String, Box<dyn Error>> {
+    Ok(serde_json::to_string(&Versioned {
+        version: SCHEMA_VERSION,
+        data,
+    })?)
+}
+
+pub fn from_str<T: DeserializeOwned>(s: &str) -> Result<T, Box<dyn Error>> {
+    Ok(from_str_versioned(s)?.0)
+}
+
+/// Like `from_str`, also returning the version the data was written with.
+pub fn from_str_versioned<T: DeserializeOwned>(s: &str) -> Result<(T, u32), Box<dyn Error>> {
+    let versioned: Versioned<T> = serde_json::from_str(s)?;
+    if versioned.version > SCHEMA_VERSION {
+        return Err(format!(
+            "File has schema version {}, but this version of russ only reads up to {}",
+            versioned.version, SCHEMA_VERSION
+        )
+        .into());
+    }
+    Ok((versioned.data, versioned.version))
+}
+
+/// The original format: `index.json` in the data dir plus one JSON file per
+/// feed, named by feed id, in the feed dir. Every change rewrites the whole
+/// feed file.
+///
+/// Loading also folds in conflicting copies left by file sync tools, so the
+/// data dir can be shared between machines.
+pub struct JsonStore {
+    paths: Paths,
+    /// Leave the files as they are when loading: conflicting copies are
+    /// merged in memory only.
+    read_only: bool,
+}
+
+impl JsonStore {
+    pub fn new(paths: Paths) -> JsonStore {
+        JsonStore {
+            paths,
+            read_only: false,
+        }
+    }
+
+    /// A store that is only loaded from, never written to.
+    pub fn read_only(paths: Paths) -> JsonStore {
+        JsonStore {
+            paths,
+            read_only: true,
+        }
+    }
+
+    pub fn exists(&self) -> bool {
+        self.paths.index().exists()
+    }
+}
+
+impl Store for JsonStore {
+    fn load(&mut self) -> Result<(Index, Vec<RussFeed>), Box<dyn Error>> {
+        if !self.exists() {
+            return Ok((Index::default(), Vec::new()));
+        }
+
+        let mut index = Index::from_file(&self.paths.index())?;
+        let copies = conflict_copies(&self.paths.data_dir, "index")?;
+        let mut renamed = Vec::new();
+        for copy in &copies {
+            renamed.extend(index.merge(Index::from_file(copy)?));
+        }
+        if !copies.is_empty() && !self.read_only {
+            index.save(&self.paths.index())?;
+            copies.iter().try_for_each(fs::remove_file)?;
+        }
+
+        let mut feeds = Vec::new();
+        for meta in &index.meta {
+            let path = self.paths.feed(&meta.id);
+            let mut feed = RussFeed::from_file(&path)?;
+            let copies = conflict_copies(&self.paths.feed_dir, &meta.id)?;
+            for copy in &copies {
+                feed.merge_replica(RussFeed::from_file(copy)?);
+            }
+            // The other copy of the index had this feed under another id.
+            let others: Vec<PathBuf> = renamed
+                .iter()
+                .filter(|(id, _)| *id == meta.id)
+                .map(|(_, other)| self.paths.feed(other))
+                .filter(|path| path.exists())
+                .collect();
+            for other in &others {
+                feed.merge_replica(RussFeed::from_file(other)?);
+            }
+            if (!copies.is_empty() || !others.is_empty()) && !self.read_only {
+                feed.save(&path)?;
+                copies.iter().chain(&others).try_for_each(fs::remove_file)?;
+            }
+            feeds.push(feed);
+        }
+        Ok((index, feeds))
+    }
+
+    fn save_index(&mut self, index: &Index) -> Result<(), Box<dyn Error>> {
+        if self.read_only {
+            return Err("The store was opened read-only".into());
+        }
+        index.save(&self.paths.index())
+    }
+
+    fn save_feed(&mut self, feed: &RussFeed) -> Result<(), Box<dyn Error>> {
+        if self.read_only {
+            return Err("The store was opened read-only".into());
+        }
+        feed.save(&self.paths.feed(&feed.meta.id))
+    }
+
+    fn save_post_state(&mut self, feed: &RussFeed, _post: &Post) -> Result<(), Box<dyn Error>> {
+        self.save_feed(feed)
+    }
+}
+
+/// Copies of `dir/stem[.json]` made by sync tools when two machines changed
+/// it at once, such as `stem.sync-conflict-20250101-120000-ABCDEFG.json`
+/// (Syncthing) or `stem (conflicted copy 2025-01-01).json` (Dropbox).
+fn conflict_copies(dir: &Path, stem: &str) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut copies = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(rest) = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.strip_prefix(stem))
+        else {
+            continue;
+        };
+        if rest.starts_with(".sync-conflict-") || rest.starts_with(" (conflicted copy") {
+            copies.push(path);
+        }
+    }
+    Ok(copies)
+}
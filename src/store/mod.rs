@@ -0,0 +1,168 @@
+use serde::Deserialize;
+use std::{
+    env,
+    error::Error,
+    fs::{self, File, TryLockError},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    app::Index,
+    feed::{Post, RussFeed},
+};
+
+pub mod json;
+pub mod sqlite;
+
+/// Persistence for the index and feeds. `App` goes through this instead of
+/// touching files directly so the on-disk format can be swapped in config.
+///
+/// Every write is atomic: an interrupted one leaves the previous state. The
+/// JSON store replaces files by renaming and backs up the index; SQLite
+/// commits each write in a single transaction and needs no backup.
+pub trait Store {
+    /// Loads the index and every feed it lists, in index order.
+    fn load(&mut self) -> Result<(Index, Vec<RussFeed>), Box<dyn Error>>;
+
+    fn save_index(&mut self, index: &Index) -> Result<(), Box<dyn Error>>;
+
+    /// Writes a whole feed: its metadata, posts and their state.
+    fn save_feed(&mut self, feed: &RussFeed) -> Result<(), Box<dyn Error>>;
+
+    /// Writes only the read/star/tag state of one post, after it changed
+    /// in the UI.
+    fn save_post_state(&mut self, feed: &RussFeed, post: &Post) -> Result<(), Box<dyn Error>>;
+}
+
+/// Where the store lives. Everything russ writes is under `data_dir`, so
+/// the directory can be synced between machines as a whole.
+#[derive(Clone)]
+pub struct Paths {
+    pub data_dir: PathBuf,
+    pub feed_dir: PathBuf,
+}
+
+impl Paths {
+    /// Resolves the configured directories. `~` expands to the home
+    /// directory, and a relative `feed_dir` is taken relative to `data_dir`.
+    pub fn new(data_dir: &str, feed_dir: &str) -> Paths {
+        let data_dir = expand(data_dir, &env::home_dir().unwrap());
+        let feed_dir = expand(feed_dir, &data_dir);
+        Paths { data_dir, feed_dir }
+    }
+
+    pub fn index(&self) -> PathBuf {
+        self.data_dir.join("index.json")
+    }
+
+    pub fn feed(&self, id: &str) -> PathBuf {
+        self.feed_dir.join(id)
+    }
+
+    pub fn database(&self) -> PathBuf {
+        self.data_dir.join("russ.db")
+    }
+
+    pub fn session(&self) -> PathBuf {
+        self.data_dir.join("session.json")
+    }
+
+    /// Downloaded images, named by the md5 of their URL.
+    pub fn images(&self) -> PathBuf {
+        self.data_dir.join("images")
+    }
+}
+
+fn expand(path: &str, base: &Path) -> PathBuf {
+    match path.strip_prefix("~/") {
+        Some(rest) => env::home_dir().unwrap().join(rest),
+        None if path == "~" => env::home_dir().unwrap(),
+        None => base.join(path),
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    #[default]
+    Sqlite,
+    Json,
+}
+
+/// Opens the configured store. A new SQLite database is seeded from the
+/// legacy JSON files if there are any.
+pub fn open(backend: Backend, paths: &Paths) -> Result<Box<dyn Store>, Box<dyn Error>> {
+    fs::create_dir_all(&paths.feed_dir)?;
+    let mut legacy = json::JsonStore::new(paths.clone());
+    match backend {
+        Backend::Json => Ok(Box::new(legacy)),
+        Backend::Sqlite => {
+            let mut store = sqlite::SqliteStore::open(&paths.database())?;
+            if store.is_empty()? && legacy.exists() {
+                import(&mut legacy, &mut store)?;
+            }
+            Ok(Box::new(store))
+        }
+    }
+}
+
+/// Opens the configured store for reading only, without writing anything:
+/// no migrations, and a database that doesn't exist yet is read from the
+/// legacy JSON files.
+pub fn open_read_only(backend: Backend, paths: &Paths) -> Result<Box<dyn Store>, Box<dyn Error>> {
+    match backend {
+        Backend::Sqlite if paths.database().exists() => Ok(Box::new(
+            sqlite::SqliteStore::open_read_only(&paths.database())?,
+        )),
+        _ => Ok(Box::new(json::JsonStore::read_only(paths.clone()))),
+    }
+}
+
+/// Copies everything in `from` into `to`. Returns the number of feeds copied.
+pub fn import(from: &mut dyn Store, to: &mut dyn Store) -> Result<usize, Box<dyn Error>> {
+    let (index, feeds) = from.load()?;
+    for feed in &feeds {
+        to.save_feed(feed)?;
+    }
+    to.save_index(&index)?;
+    Ok(feeds.len())
+}
+
+/// Replaces `path` with `contents` without ever leaving a partly written
+/// file behind: the data goes to a temporary file in the same directory,
+/// which is synced and then renamed over the original.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp = dir.join(tmp_name);
+
+    let mut file = File::create(&tmp)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)?;
+
+    // Make the rename itself durable.
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+
+    Ok(())
+}
+
+/// Takes an exclusive advisory lock on the data directory so a TUI and a
+/// `russ refresh` from cron never write the store at the same time. The
+/// lock is released when the returned file is dropped.
+pub fn lock_data_dir(data_dir: &Path) -> Result<File, Box<dyn Error>> {
+    fs::create_dir_all(data_dir)?;
+    let file = File::create(data_dir.join("lock"))?;
+    match file.try_lock() {
+        Ok(()) => Ok(file),
+        Err(TryLockError::WouldBlock) => Err(format!(
+            "Another russ process is using {}; try again when it exits",
+            data_dir.display()
+        )
+        .into()),
+        Err(TryLockError::Error(e)) => Err(e.into()),
+    }
+}
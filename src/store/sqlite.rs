@@ -0,0 +1,332 @@
+use chrono::{DateTime, FixedOffset, Utc};
+use rusqlite::{Connection, OpenFlags, OptionalExtension, params};
+use std::{error::Error, path::Path};
+
+use crate::{
+    app::Index,
+    feed::{ContentType, Metadata, Post, RussFeed},
+    store::Store,
+};
+
+/// Schema changes, applied in order. `PRAGMA user_version` records how many
+/// have run, so only append to this list.
+const MIGRATIONS: &[&str] = &[
+    r#"
+    CREATE TABLE feeds (
+        id TEXT PRIMARY KEY,
+        position INTEGER NOT NULL,
+        title TEXT NOT NULL,
+        url TEXT NOT NULL,
+        source TEXT NOT NULL,
+        pruned TEXT NOT NULL DEFAULT '[]'
+    );
+    CREATE TABLE posts (
+        feed_id TEXT NOT NULL REFERENCES feeds(id) ON DELETE CASCADE,
+        key TEXT NOT NULL,
+        position INTEGER NOT NULL,
+        id TEXT NOT NULL,
+        title TEXT NOT NULL,
+        link TEXT NOT NULL,
+        authors TEXT NOT NULL,
+        categories TEXT NOT NULL,
+        published TEXT,
+        content TEXT NOT NULL,
+        PRIMARY KEY (feed_id, key)
+    );
+    CREATE TABLE post_state (
+        feed_id TEXT NOT NULL,
+        key TEXT NOT NULL,
+        read INTEGER NOT NULL DEFAULT 0,
+        starred INTEGER NOT NULL DEFAULT 0,
+        hidden INTEGER NOT NULL DEFAULT 0,
+        tags TEXT NOT NULL DEFAULT '[]',
+        highlight TEXT,
+        PRIMARY KEY (feed_id, key),
+        FOREIGN KEY (feed_id, key) REFERENCES posts(feed_id, key) ON DELETE CASCADE
+    );
+    CREATE TABLE fetches (
+        feed_id TEXT PRIMARY KEY REFERENCES feeds(id) ON DELETE CASCADE,
+        fetched_at TEXT,
+        error TEXT
+    );
+"#,
+    r#"
+    ALTER TABLE post_state ADD COLUMN changed TEXT;
+"#,
+    r#"
+    ALTER TABLE feeds ADD COLUMN remote TEXT;
+"#,
+    r#"
+    ALTER TABLE feeds ADD COLUMN category TEXT;
+"#,
+    r#"
+    ALTER TABLE posts ADD COLUMN images TEXT NOT NULL DEFAULT '[]';
+"#,
+    r#"
+    ALTER TABLE posts ADD COLUMN extracted TEXT;
+"#,
+    r#"
+    ALTER TABLE posts ADD COLUMN content_type TEXT NOT NULL DEFAULT 'html';
+"#,
+    r#"
+    ALTER TABLE post_state ADD COLUMN server_starred INTEGER;
+"#,
+    // Highlights were stored JSON-encoded; colour names need no escaping,
+    // so dropping the quotes is enough.
+    r#"
+    UPDATE post_state SET highlight = substr(highlight, 2, length(highlight) - 2)
+    WHERE highlight LIKE '"%"';
+"#,
+];
+
+pub struct SqliteStore {
+    conn: Connection,
+}
+
+impl SqliteStore {
+    pub fn open(path: &Path) -> Result<SqliteStore, Box<dyn Error>> {
+        let mut conn = Connection::open(path)?;
+        conn.pragma_update(None, "foreign_keys", true)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        migrate(&mut conn)?;
+        Ok(SqliteStore { conn })
+    }
+
+    /// Opens an existing database without changing it. Its schema must
+    /// be up to date, since migrating would write to it.
+    pub fn open_read_only(path: &Path) -> Result<SqliteStore, Box<dyn Error>> {
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if version as usize != MIGRATIONS.len() {
+            return Err(format!(
+                "Database schema version {version} isn't the current one; open it with russ to upgrade it"
+            )
+            .into());
+        }
+        Ok(SqliteStore { conn })
+    }
+
+    pub fn is_empty(&self) -> Result<bool, Box<dyn Error>> {
+        let count: i64 = self
+            .conn
+            .query_row("SELECT COUNT(*) FROM feeds", [], |row| row.get(0))?;
+        Ok(count == 0)
+    }
+}
+
+fn migrate(conn: &mut Connection) -> Result<(), Box<dyn Error>> {
+    let version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    let version = version as usize;
+    if version > MIGRATIONS.len() {
+        return Err(format!(
+            "Database schema version {version} is newer than this version of russ supports"
+        )
+        .into());
+    }
+
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", (i + 1) as i64)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+impl Store for SqliteStore {
+    fn load(&mut self) -> Result<(Index, Vec<RussFeed>), Box<dyn Error>> {
+        let mut feeds = Vec::new();
+
+        let mut feed_stmt = self.conn.prepare(
+            "SELECT f.id, f.title, f.url, f.source, f.pruned, x.fetched_at, x.error, f.remote,
+                    f.category
+             FROM feeds f LEFT JOIN fetches x ON x.feed_id = f.id
+             ORDER BY f.position",
+        )?;
+        let mut post_stmt = self.conn.prepare(
+            "SELECT p.id, p.title, p.link, p.authors, p.categories, p.published, p.content,
+                    s.read, s.starred, s.hidden, s.tags, s.highlight, s.changed, p.images,
+                    p.extracted, p.content_type, s.server_starred
+             FROM posts p LEFT JOIN post_state s ON s.feed_id = p.feed_id AND s.key = p.key
+             WHERE p.feed_id = ?1
+             ORDER BY p.position",
+        )?;
+
+        let rows = feed_stmt.query_map([], |row| {
+            Ok((
+                Metadata {
+                    id: row.get(0)?,
+                    title: row.get(1)?,
+                    url: row.get(2)?,
+                    source: row.get(3)?,
+                    fetched_at: row.get::<_, Option<DateTime<Utc>>>(5)?,
+                    fetch_error: row.get(6)?,
+                    remote: row.get(7)?,
+                    category: row.get(8)?,
+                },
+                row.get::<_, String>(4)?,
+            ))
+        })?;
+
+        for row in rows {
+            let (meta, pruned) = row?;
+            let posts = post_stmt
+                .query_map([&meta.id], |row| {
+                    Ok(Post {
+                        id: row.get(0)?,
+                        title: row.get(1)?,
+                        link: row.get(2)?,
+                        authors: from_json(row.get(3)?),
+                        categories: from_json(row.get(4)?),
+                        published: row.get::<_, Option<DateTime<FixedOffset>>>(5)?,
+                        content: row.get(6)?,
+                        read: row.get::<_, Option<bool>>(7)?.unwrap_or(false),
+                        starred: row.get::<_, Option<bool>>(8)?.unwrap_or(false),
+                        hidden: row.get::<_, Option<bool>>(9)?.unwrap_or(false),
+                        tags: from_json(row.get::<_, Option<String>>(10)?.unwrap_or_default()),
+                        highlight: row.get(11)?,
+                        state_changed: row.get::<_, Option<DateTime<Utc>>>(12)?,
+                        images: from_json(row.get(13)?),
+                        extracted: row.get(14)?,
+                        content_type: ContentType::from_name(&row.get::<_, String>(15)?),
+                        server_starred: row.get(16)?,
+                    })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            feeds.push(RussFeed {
+                meta,
+                posts,
+                pruned: from_json(pruned),
+            });
+        }
+
+        let index = Index {
+            meta: feeds.iter().map(|f| f.meta.clone()).collect(),
+        };
+        Ok((index, feeds))
+    }
+
+    fn save_index(&mut self, index: &Index) -> Result<(), Box<dyn Error>> {
+        let tx = self.conn.transaction()?;
+        let ids: Vec<String> = index.meta.iter().map(|m| m.id.clone()).collect();
+        tx.execute(
+            "DELETE FROM feeds WHERE id NOT IN (SELECT value FROM json_each(?1))",
+            [serde_json::to_string(&ids)?],
+        )?;
+        for (position, meta) in index.meta.iter().enumerate() {
+            tx.execute(
+                "UPDATE feeds SET position = ?2, title = ?3, url = ?4, source = ?5 WHERE id = ?1",
+                params![meta.id, position as i64, meta.title, meta.url, meta.source],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn save_feed(&mut self, feed: &RussFeed) -> Result<(), Box<dyn Error>> {
+        let tx = self.conn.transaction()?;
+        let meta = &feed.meta;
+
+        let position: Option<i64> = tx
+            .query_row(
+                "SELECT position FROM feeds WHERE id = ?1",
+                [&meta.id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let position = match position {
+            Some(p) => p,
+            None => tx.query_row(
+                "SELECT COALESCE(MAX(position) + 1, 0) FROM feeds",
+                [],
+                |row| row.get(0),
+            )?,
+        };
+
+        tx.execute(
+            "INSERT INTO feeds (id, position, title, url, source, pruned, remote, category)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+             ON CONFLICT(id) DO UPDATE SET
+                title = excluded.title, url = excluded.url, source = excluded.source,
+                pruned = excluded.pruned, remote = excluded.remote,
+                category = excluded.category",
+            params![
+                meta.id,
+                position,
+                meta.title,
+                meta.url,
+                meta.source,
+                serde_json::to_string(&feed.pruned)?,
+                meta.remote,
+                meta.category
+            ],
+        )?;
+        tx.execute(
+            "INSERT INTO fetches (feed_id, fetched_at, error) VALUES (?1, ?2, ?3)
+             ON CONFLICT(feed_id) DO UPDATE SET
+                fetched_at = excluded.fetched_at, error = excluded.error",
+            params![meta.id, meta.fetched_at, meta.fetch_error],
+        )?;
+
+        // Keys are unique once `RussFeed::merge` has run, so a collision is
+        // an error rather than a post to drop.
+        tx.execute("DELETE FROM posts WHERE feed_id = ?1", [&meta.id])?;
+        for (position, post) in feed.posts.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO posts
+                    (feed_id, key, position, id, title, link, authors, categories, published, content,
+                     images, extracted, content_type)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+                params![
+                    meta.id,
+                    post.key(),
+                    position as i64,
+                    post.id,
+                    post.title,
+                    post.link,
+                    serde_json::to_string(&post.authors)?,
+                    serde_json::to_string(&post.categories)?,
+                    post.published,
+                    post.content,
+                    serde_json::to_string(&post.images)?,
+                    post.extracted,
+                    post.content_type.as_str()
+                ],
+            )?;
+            insert_state(&tx, &meta.id, post)?;
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    // A single statement, so it is atomic without a transaction.
+    fn save_post_state(&mut self, feed: &RussFeed, post: &Post) -> Result<(), Box<dyn Error>> {
+        insert_state(&self.conn, &feed.meta.id, post)
+    }
+}
+
+fn insert_state(conn: &Connection, feed_id: &str, post: &Post) -> Result<(), Box<dyn Error>> {
+    conn.execute(
+        "INSERT OR REPLACE INTO post_state
+            (feed_id, key, read, starred, hidden, tags, highlight, changed, server_starred)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            feed_id,
+            post.key(),
+            post.read,
+            post.starred,
+            post.hidden,
+            serde_json::to_string(&post.tags)?,
+            post.highlight,
+            post.state_changed,
+            post.server_starred
+        ],
+    )?;
+    Ok(())
+}
+
+fn from_json(value: String) -> Vec<String> {
+    serde_json::from_str(&value).unwrap_or_default()
+}
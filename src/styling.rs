@@ -1,93 +1,1318 @@
-use html5ever::tendril::TendrilSink;
+use html5ever::{QualName, local_name, ns, tendril::TendrilSink};
 use markup5ever_rcdom::{Handle, NodeData, RcDom};
 use ratatui::{
-    style::{Color, Modifier, Style},
+    style::{Modifier, Style},
     text::{Line, Span, Text},
 };
-// use ratatui_image::{StatefulImage, picker::Picker, protocol::StatefulProtocol};
+use reqwest::Url;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
-pub fn html_to_ratatui<'a>(mut html: &'a [u8]) -> Text<'a> {
-    let dom = html5ever::parse_document(RcDom::default(), Default::default())
+use crate::{feed::ContentType, highlight, sanitize, theme::Theme};
+
+/// Narrowest layout width; below this nested blocks have no room left.
+const MIN_WIDTH: usize = 20;
+/// Width used to measure a table cell's natural size.
+const UNBOUNDED: usize = 1000;
+/// Rows kept free for each image; the image is scaled to fit them.
+const IMAGE_ROWS: usize = 12;
+/// Images declared this many pixels wide or high or smaller are icons or
+/// spacers and only get their alt text.
+const SMALL_IMAGE: u32 = 32;
+
+/// How `html_to_ratatui` lays out an article.
+#[derive(Default, Hash)]
+pub struct Options<'a> {
+    pub width: u16,
+    /// The post's link; relative link and image targets are resolved
+    /// against it.
+    pub base: &'a str,
+    /// Columns highlighted code blocks are scrolled right by.
+    pub hscroll: u16,
+    /// Keep rows free for images instead of showing only their alt text.
+    pub images: bool,
+    /// Plain text: no colours, highlighting or images.
+    pub plain: bool,
+    pub content_type: ContentType,
+    pub theme: Theme,
+    /// Image enclosures, shown first unless the content has them too.
+    pub enclosures: &'a [String],
+}
+
+/// An article laid out for the reader.
+pub struct Article {
+    pub text: Text<'static>,
+    /// Images to draw over the rows kept for them in `text`.
+    pub images: Vec<ImageSlot>,
+    /// Where the links are, for clicking them.
+    pub links: Vec<LinkArea>,
+    /// Targets of the links, in the order they are numbered.
+    pub targets: Vec<String>,
+    /// Furthest code blocks scroll right before the widest line ends.
+    pub hscroll_max: usize,
+}
+
+impl Article {
+    /// Target of the link drawn at `column` of `line`, if any.
+    pub fn link_at(&self, line: usize, column: usize) -> Option<&str> {
+        self.links
+            .iter()
+            .find(|area| area.line == line && (area.start..area.end).contains(&column))
+            .map(|area| area.url.as_str())
+    }
+}
+
+/// Columns `start..end` of `line` show a link to `url`: its number and
+/// the underlined text just before it.
+pub struct LinkArea {
+    pub line: usize,
+    pub start: usize,
+    pub end: usize,
+    pub url: String,
+}
+
+/// The article last laid out for the reader, with its header. Drawing
+/// happens on every key press, so the layout is kept until the post, its
+/// body, the header or the options change, which includes the reader being
+/// resized. Hashing the body is far cheaper than laying it out again.
+pub struct ArticleCache {
+    key: u64,
+    /// The header, wrapped like the article so each line is one row.
+    pub header: Vec<Line<'static>>,
+    pub article: Article,
+}
+
+impl ArticleCache {
+    /// The cached layout of `html`, the body shown for the post `post_key`
+    /// of feed `feed_id`, below `header`. It is laid out again if anything
+    /// it depends on changed.
+    pub fn get<'c>(
+        cache: &'c mut Option<ArticleCache>,
+        feed_id: &str,
+        post_key: &str,
+        header: Text<'static>,
+        html: &str,
+        options: &Options,
+    ) -> &'c ArticleCache {
+        let mut hasher = DefaultHasher::new();
+        (feed_id, post_key, &header, html, options).hash(&mut hasher);
+        let key = hasher.finish();
+
+        if cache.as_ref().is_none_or(|c| c.key != key) {
+            let width = usize::from(options.width).max(MIN_WIDTH);
+            let header = header
+                .lines
+                .into_iter()
+                .flat_map(|line| match wrap(line.spans, width) {
+                    lines if lines.is_empty() => vec![Line::default()],
+                    lines => lines.into_iter().map(Line::from).collect(),
+                })
+                .collect();
+            let article = html_to_ratatui(html.as_bytes(), options);
+            *cache = Some(ArticleCache {
+                key,
+                header,
+                article,
+            });
+        }
+        cache.as_ref().unwrap()
+    }
+
+    /// Rows the header and article take.
+    pub fn rows(&self) -> usize {
+        self.header.len() + self.article.text.lines.len()
+    }
+
+    /// The `height` rows from `top`, to draw without wrapping them again.
+    pub fn window(&self, top: usize, height: usize) -> Text<'static> {
+        self.header
+            .iter()
+            .chain(&self.article.text.lines)
+            .skip(top)
+            .take(height)
+            .cloned()
+            .collect()
+    }
+}
+
+/// Where an image goes: `height` rows from `line`, `width` columns from
+/// `column`. The first row holds a placeholder with the alt text.
+pub struct ImageSlot {
+    pub url: String,
+    pub line: usize,
+    pub column: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// Renders a post's content, of `options.content_type`, as styled text laid
+/// out for `options.width` columns.
+/// Wrapping is done here rather than by the `Paragraph`, so that list
+/// markers, quote gutters and table columns stay lined up.
+///
+/// Each link is numbered after its text and listed with its target at the
+/// end. Highlighted code blocks are not wrapped; they are cut to the width
+/// after skipping `options.hscroll` columns.
+pub fn html_to_ratatui(html: &[u8], options: &Options) -> Article {
+    let mut renderer = render(html, options);
+    renderer.references();
+    renderer.flush();
+    let images = std::mem::take(&mut renderer.images);
+    let links = std::mem::take(&mut renderer.link_areas);
+    let targets = std::mem::take(&mut renderer.links);
+    let hscroll_max = renderer.overflow;
+    let mut text = renderer.finish();
+    if options.plain {
+        for span in text.lines.iter_mut().flat_map(|line| line.spans.iter_mut()) {
+            span.style = Style::default();
+        }
+    }
+    Article {
+        text,
+        images,
+        links,
+        targets,
+        hscroll_max,
+    }
+}
+
+fn render(content: &[u8], options: &Options) -> Renderer {
+    let dom = match options.content_type {
+        ContentType::Html => {
+            let dom = parse(content);
+            if sanitize::is_escaped(&dom.document) {
+                parse(text_content(&dom.document).as_bytes())
+            } else {
+                dom
+            }
+        }
+        ContentType::Xhtml => parse_fragment(content),
+        ContentType::Text => parse(text_to_html(&String::from_utf8_lossy(content)).as_bytes()),
+        ContentType::Markdown => {
+            let markdown = String::from_utf8_lossy(content);
+            let mut html = String::new();
+            pulldown_cmark::html::push_html(
+                &mut html,
+                pulldown_cmark::Parser::new_ext(&markdown, pulldown_cmark::Options::all()),
+            );
+            parse(html.as_bytes())
+        }
+    };
+    sanitize::sanitize(&dom.document);
+
+    let mut renderer = Renderer::new(
+        usize::from(options.width).max(MIN_WIDTH),
+        Url::parse(options.base).ok(),
+    );
+    renderer.hscroll = usize::from(options.hscroll);
+    renderer.show_images = options.images && !options.plain;
+    renderer.highlight = !options.plain;
+    renderer.theme = options.theme;
+    let shown = String::from_utf8_lossy(content);
+    for url in options
+        .enclosures
+        .iter()
+        .filter(|url| !shown.contains(url.as_str()))
+    {
+        renderer.enclosure(url);
+    }
+    renderer.node(&dom.document, options.theme.text);
+    renderer
+}
+
+pub fn parse(mut html: &[u8]) -> RcDom {
+    html5ever::parse_document(RcDom::default(), Default::default())
         .from_utf8()
         .read_from(&mut html)
-        .unwrap();
+        .unwrap()
+}
+
+/// Parses markup that is the inside of an element rather than a page.
+fn parse_fragment(mut html: &[u8]) -> RcDom {
+    let context = QualName::new(None, ns!(html), local_name!("div"));
+    html5ever::parse_fragment(
+        RcDom::default(),
+        Default::default(),
+        context,
+        Vec::new(),
+        false,
+    )
+    .from_utf8()
+    .read_from(&mut html)
+    .unwrap()
+}
+
+/// Plain text as HTML: blank lines separate paragraphs and other line
+/// breaks are kept.
+fn text_to_html(text: &str) -> String {
+    let escaped = text
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace("\r\n", "\n");
+    escaped
+        .split("\n\n")
+        .filter(|paragraph| !paragraph.trim().is_empty())
+        .map(|paragraph| {
+            format!(
+                "<p>{}</p>",
+                paragraph.trim_matches('\n').replace('\n', "<br>")
+            )
+        })
+        .collect()
+}
+
+/// Prefix added to each line of a block: a list marker, a quote gutter or
+/// plain indentation. `first` goes on the block's first line, `rest` on the
+/// lines after it.
+struct Indent {
+    first: Span<'static>,
+    rest: Span<'static>,
+    used: bool,
+}
+
+impl Indent {
+    fn new(first: String, rest: String, style: Style) -> Indent {
+        Indent {
+            first: Span::styled(first, style),
+            rest: Span::styled(rest, style),
+            used: false,
+        }
+    }
+}
 
-    fn dom_to_ratatui(node: &Handle, parent_style: Style) -> Text<'static> {
+/// A link in the inline content: its text starts at the `text`th non-space
+/// character laid out, and its number takes the ones from `number` to
+/// `end`. Counting characters rather than spans finds them after wrapping.
+struct LinkMark {
+    text: usize,
+    number: usize,
+    end: usize,
+    url: String,
+}
+
+struct Renderer {
+    width: usize,
+    lines: Vec<Line<'static>>,
+    /// Inline content of the block being built.
+    inline: Vec<Span<'static>>,
+    /// Whitespace was seen since the last word.
+    space: bool,
+    indents: Vec<Indent>,
+    /// Inside `<pre>`: keep whitespace and break lines only at newlines.
+    pre: bool,
+    /// A blank line is owed before the next line.
+    gap: bool,
+    /// Open lists, innermost last: the next number for `<ol>`, `None` for
+    /// `<ul>`.
+    lists: Vec<Option<usize>>,
+    /// Link targets seen so far; a link's number is its position plus one.
+    links: Vec<String>,
+    /// Links numbered in `inline`, placed when it is laid out.
+    marks: Vec<LinkMark>,
+    /// Non-space characters laid out so far.
+    laid_out: usize,
+    link_areas: Vec<LinkArea>,
+    base: Option<Url>,
+    /// Columns highlighted code blocks are scrolled right by.
+    hscroll: usize,
+    /// Columns the widest highlighted code line runs past its block.
+    overflow: usize,
+    show_images: bool,
+    images: Vec<ImageSlot>,
+    /// Highlight code blocks whose language is known.
+    highlight: bool,
+    theme: Theme,
+}
+
+impl Renderer {
+    fn new(width: usize, base: Option<Url>) -> Renderer {
+        Renderer {
+            width,
+            lines: Vec::new(),
+            inline: Vec::new(),
+            space: false,
+            indents: Vec::new(),
+            pre: false,
+            gap: false,
+            lists: Vec::new(),
+            links: Vec::new(),
+            marks: Vec::new(),
+            laid_out: 0,
+            link_areas: Vec::new(),
+            base,
+            hscroll: 0,
+            overflow: 0,
+            show_images: false,
+            images: Vec::new(),
+            highlight: false,
+            theme: Theme::default(),
+        }
+    }
+
+    fn finish(mut self) -> Text<'static> {
+        self.flush();
+        Text::from(self.lines)
+    }
+
+    fn node(&mut self, node: &Handle, style: Style) {
         match &node.data {
-            NodeData::Text { contents } => {
-                let s: String = contents.borrow().to_string();
-                let mut text = Text::default();
-                text.push_line(Line::from(Span::styled(s, parent_style)));
-                text
-            }
-            NodeData::Element { name, .. } => {
-                let mut text = Text::default();
-                let mut style = parent_style;
-                match name.local.as_ref() {
-                    "h1" => {
-                        style = style
-                            .add_modifier(Modifier::BOLD)
-                            .add_modifier(Modifier::UNDERLINED);
+            NodeData::Document => self.children(node, style),
+            NodeData::Text { contents } => self.text(&contents.borrow(), style),
+            NodeData::Element { name, .. } => self.element(node, name.local.as_ref(), style),
+            _ => {}
+        }
+    }
+
+    fn children(&mut self, node: &Handle, style: Style) {
+        for child in node.children.borrow().iter() {
+            self.node(child, style);
+        }
+    }
+
+    fn element(&mut self, node: &Handle, tag: &str, style: Style) {
+        match tag {
+            "style" | "head" => {}
+            "b" | "strong" => self.children(node, style.add_modifier(Modifier::BOLD)),
+            "em" | "i" | "cite" => self.children(node, style.add_modifier(Modifier::ITALIC)),
+            "u" | "ins" => self.children(node, style.add_modifier(Modifier::UNDERLINED)),
+            "s" | "strike" | "del" => {
+                self.children(node, style.add_modifier(Modifier::CROSSED_OUT))
+            }
+            "mark" => self.children(node, style.patch(self.theme.mark)),
+            "code" | "kbd" | "samp" | "tt" => self.children(node, style.patch(self.theme.code)),
+            "a" => {
+                let before = self.inline.len();
+                let text = self.offset();
+                self.children(node, style.patch(self.theme.link));
+                if let Some(n) = attr(node, "href").and_then(|href| self.link(&href)) {
+                    // A link with no text (an image, say) still gets its
+                    // number, set off from the word before it.
+                    let mut marker = format!("[{n}]");
+                    if self.inline.len() == before && self.space && before > 0 && !self.pre {
+                        marker.insert(0, ' ');
+                        self.space = false;
                     }
-                    "b" | "strong" => {
-                        style = style.add_modifier(Modifier::BOLD);
+                    let number = self.offset();
+                    self.inline
+                        .push(Span::styled(marker, self.theme.link_number));
+                    self.marks.push(LinkMark {
+                        text,
+                        number,
+                        end: self.offset(),
+                        url: self.links[n - 1].clone(),
+                    });
+                }
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.paragraph(node, heading_style(tag, style.patch(self.theme.heading)));
+            }
+            "p" => self.paragraph(node, style),
+            "br" => self.line_break(),
+            "img" => self.image(node),
+            "hr" => {
+                self.block(true);
+                let width = self.available();
+                self.push_line(vec![Span::styled("─".repeat(width), self.theme.gutter)]);
+                self.block(true);
+            }
+            "pre" => {
+                self.block(true);
+                let code = text_content(node);
+                let language = code_language(node)
+                    .or_else(|| highlight::guess_language(&code))
+                    .filter(|_| self.highlight);
+                let lines = language
+                    .zip(self.theme.syntax)
+                    .and_then(|(language, theme)| highlight::highlight(&code, &language, theme));
+                match lines {
+                    Some(lines) => {
+                        let width = self.available();
+                        for line in lines {
+                            let line_width: usize = line.iter().map(Span::width).sum();
+                            self.overflow = self.overflow.max(line_width.saturating_sub(width));
+                            self.push_line(crop(line, self.hscroll, width));
+                        }
                     }
-                    "em" => {
-                        style = style.add_modifier(Modifier::ITALIC);
+                    None => {
+                        self.pre = true;
+                        self.children(node, style.patch(self.theme.code));
+                        self.flush_pre();
+                        self.pre = false;
                     }
-                    "a" => {
-                        style = style.add_modifier(Modifier::UNDERLINED).fg(Color::Blue);
+                }
+                self.block(true);
+            }
+            "blockquote" => {
+                self.block(true);
+                self.indents
+                    .push(Indent::new("│ ".into(), "│ ".into(), self.theme.gutter));
+                self.children(node, style.patch(self.theme.quote));
+                self.flush();
+                self.indents.pop();
+                self.gap = true;
+            }
+            "ul" | "ol" => {
+                // Nested lists sit directly under their item.
+                let top = self.lists.is_empty();
+                self.block(top);
+                self.lists.push(if tag == "ol" {
+                    Some(
+                        attr(node, "start")
+                            .and_then(|s| s.parse().ok())
+                            .unwrap_or(1),
+                    )
+                } else {
+                    None
+                });
+                self.children(node, style);
+                self.flush();
+                self.lists.pop();
+                self.block(top);
+            }
+            "li" => {
+                self.flush();
+                let marker = match self.lists.last_mut() {
+                    Some(Some(n)) => {
+                        *n += 1;
+                        format!("{}. ", *n - 1)
                     }
-                    "style" => return Text::default(),
-                    _ => {}
+                    _ => format!("{} ", bullet(self.lists.len())),
+                };
+                let rest = " ".repeat(marker.width());
+                self.indents
+                    .push(Indent::new(marker, rest, self.theme.gutter));
+                self.children(node, style);
+                self.flush();
+                self.indents.pop();
+            }
+            "dl" => self.paragraph(node, style),
+            "dt" => {
+                self.flush();
+                self.children(node, style.add_modifier(Modifier::BOLD));
+                self.flush();
+            }
+            "dd" => {
+                self.flush();
+                self.indents
+                    .push(Indent::new("    ".into(), "    ".into(), style));
+                self.children(node, style);
+                self.flush();
+                self.indents.pop();
+            }
+            "details" | "figure" => self.paragraph(node, style),
+            "summary" => {
+                self.flush();
+                self.inline.push(Span::styled("▾ ", self.theme.gutter));
+                self.children(node, style.add_modifier(Modifier::BOLD));
+                self.flush();
+            }
+            "figcaption" => {
+                self.flush();
+                self.children(node, style.add_modifier(Modifier::ITALIC));
+                self.flush();
+            }
+            "table" => {
+                self.block(true);
+                self.table(node, style);
+                self.block(true);
+            }
+            "div" | "section" | "article" | "main" | "header" | "footer" | "nav" | "aside"
+            | "address" | "center" | "form" | "html" | "body" => {
+                self.flush();
+                self.children(node, style);
+                self.flush();
+            }
+            _ => self.children(node, style),
+        }
+    }
+
+    /// Number for the link to `href`, reusing the number of an earlier link
+    /// to the same place. In-page anchors and scripts get none.
+    fn link(&mut self, href: &str) -> Option<usize> {
+        let href = href.trim();
+        if href.is_empty() || href.starts_with('#') || href.starts_with("javascript:") {
+            return None;
+        }
+        let target = sanitize::strip_tracking(&self.resolve(href));
+        match self.links.iter().position(|l| *l == target) {
+            Some(i) => Some(i + 1),
+            None => {
+                self.links.push(target);
+                Some(self.links.len())
+            }
+        }
+    }
+
+    /// Resolves `href` against the post's link.
+    fn resolve(&self, href: &str) -> String {
+        match &self.base {
+            Some(base) => base
+                .join(href)
+                .map(String::from)
+                .unwrap_or(href.to_string()),
+            None => href.to_string(),
+        }
+    }
+
+    /// Keeps rows free for an image on a line of its own, with its alt text
+    /// as a placeholder. Small images, and all of them when images are off,
+    /// leave just the alt text in the flow.
+    fn image(&mut self, node: &Handle) {
+        let alt = attr(node, "alt").unwrap_or_default();
+        let src = attr(node, "src").filter(|s| !s.trim().is_empty());
+        let small = ["width", "height"].iter().any(|name| {
+            attr(node, name)
+                .and_then(|v| v.trim_end_matches("px").parse::<u32>().ok())
+                .is_some_and(|v| v <= SMALL_IMAGE)
+        });
+
+        let Some(src) = src.filter(|_| self.show_images && !small && !self.pre) else {
+            if !alt.trim().is_empty() {
+                self.text(&format!("[{}]", alt.trim()), self.theme.gutter);
+            }
+            return;
+        };
+
+        self.place_image(&src, alt.trim());
+    }
+
+    /// An image enclosure, placed like an image in the content, or as a
+    /// placeholder line when images aren't shown.
+    fn enclosure(&mut self, url: &str) {
+        if self.show_images {
+            self.place_image(url, "");
+        } else {
+            self.push_line(vec![Span::styled("[image]", self.theme.gutter)]);
+        }
+    }
+
+    /// Keeps `IMAGE_ROWS` rows free for the image at `src`, starting with a
+    /// placeholder line.
+    fn place_image(&mut self, src: &str, alt: &str) {
+        self.flush();
+        let label = if alt.is_empty() {
+            "[image]".to_string()
+        } else {
+            format!("[image: {alt}]")
+        };
+        let width = self.available();
+        self.push_line(vec![Span::styled(label, self.theme.gutter)]);
+        let line = self.lines.len() - 1;
+        for _ in 1..IMAGE_ROWS {
+            self.push_line(Vec::new());
+        }
+        self.images.push(ImageSlot {
+            url: self.resolve(src),
+            line,
+            column: self.width.saturating_sub(width),
+            width,
+            height: IMAGE_ROWS,
+        });
+    }
+
+    /// Lists the numbered links below a rule at the end of the article.
+    fn references(&mut self) {
+        if self.links.is_empty() {
+            return;
+        }
+        self.block(true);
+        let width = self.available();
+        self.push_line(vec![Span::styled("─".repeat(width), self.theme.gutter)]);
+        self.block(true);
+
+        let digits = self.links.len().to_string().len();
+        for (i, link) in std::mem::take(&mut self.links).into_iter().enumerate() {
+            let marker = format!("{:<w$} ", format!("[{}]", i + 1), w = digits + 2);
+            let rest = " ".repeat(marker.width());
+            let start: usize = self.indents.iter().map(|i| i.rest.width()).sum();
+            let end = start + marker.trim_end().width();
+            self.indents
+                .push(Indent::new(marker, rest, self.theme.link_number));
+            self.inline
+                .push(Span::styled(link.clone(), self.theme.gutter));
+            if let Some(line) = self.flush() {
+                self.link_areas.push(LinkArea {
+                    line,
+                    start,
+                    end,
+                    url: link.clone(),
+                });
+            }
+            self.indents.pop();
+            self.links.push(link);
+        }
+    }
+
+    /// A block set off from its neighbours by blank lines.
+    fn paragraph(&mut self, node: &Handle, style: Style) {
+        self.block(true);
+        self.children(node, style);
+        self.block(true);
+    }
+
+    /// Ends the current block, optionally owing a blank line before the
+    /// next one.
+    fn block(&mut self, gap: bool) {
+        self.flush();
+        self.gap |= gap;
+    }
+
+    fn text(&mut self, text: &str, style: Style) {
+        if self.pre {
+            for (i, part) in text.split('\n').enumerate() {
+                if i > 0 {
+                    self.flush_pre_line();
                 }
-                for child in &node.children.clone().into_inner() {
-                    let is_block = if let NodeData::Element { name, .. } = &child.data {
-                        matches!(
-                            name.local.as_ref(),
-                            "h1" | "p" | "br" | "main" | "div" | "html" | "body"
-                        )
-                    } else {
-                        false
-                    };
-
-                    let child_text = dom_to_ratatui(child, style);
-
-                    if child_text.lines.is_empty() {
-                        continue;
-                    };
-
-                    if is_block {
-                        text.push_line(Line::default());
-                        for line in child_text.lines {
-                            text.push_line(line);
-                        }
-                    } else {
-                        if text.lines.is_empty() {
-                            text.push_line(Line::default())
-                        }
-                        for line in child_text.lines {
-                            for span in line.spans {
-                                text.push_span(span);
-                            }
-                        }
+                if !part.is_empty() {
+                    let part = part.replace('\t', &" ".repeat(highlight::TAB_WIDTH));
+                    self.inline.push(Span::styled(part, style));
+                }
+            }
+            return;
+        }
+
+        // Collapse runs of whitespace to one space, dropping it at the
+        // start of a block. Only ASCII whitespace counts, so `&nbsp;` keeps
+        // words together; soft hyphens and zero-width spaces are dropped.
+        let mut collapsed = String::new();
+        for c in text.chars() {
+            if matches!(c, '\u{ad}' | '\u{200b}' | '\u{feff}') {
+                continue;
+            }
+            if c.is_ascii_whitespace() {
+                self.space = true;
+            } else {
+                if self.space && !(self.inline.is_empty() && collapsed.is_empty()) {
+                    collapsed.push(' ');
+                }
+                self.space = false;
+                collapsed.push(c);
+            }
+        }
+        if !collapsed.is_empty() {
+            self.inline.push(Span::styled(collapsed, style));
+        }
+    }
+
+    fn line_break(&mut self) {
+        if self.pre {
+            self.flush_pre_line();
+        } else if self.inline.is_empty() {
+            self.push_line(Vec::new());
+        } else {
+            self.flush();
+        }
+    }
+
+    /// Columns left for content after the current indentation.
+    fn available(&self) -> usize {
+        let indent: usize = self.indents.iter().map(|i| i.rest.width()).sum();
+        self.width.saturating_sub(indent).max(1)
+    }
+
+    /// Wraps the pending inline content into lines. Returns the row of the
+    /// first, if there was anything to wrap.
+    fn flush(&mut self) -> Option<usize> {
+        self.space = false;
+        if self.inline.is_empty() {
+            return None;
+        }
+        let spans = std::mem::take(&mut self.inline);
+        let lines = wrap(spans, self.available());
+        self.push_inline(lines)
+    }
+
+    fn flush_pre_line(&mut self) {
+        let spans = std::mem::take(&mut self.inline);
+        if spans.is_empty() {
+            self.push_line(Vec::new());
+        }
+        let lines = hard_wrap(spans, self.available());
+        self.push_inline(lines);
+    }
+
+    /// Non-space characters laid out and pending: where the next span
+    /// pushed to `inline` starts.
+    fn offset(&self) -> usize {
+        let pending = self.inline.iter().flat_map(|s| s.content.chars());
+        self.laid_out + pending.filter(|c| *c != ' ').count()
+    }
+
+    /// Pushes lines of wrapped inline content, recording where the link
+    /// numbers in them ended up. Returns the row of the first line.
+    fn push_inline(&mut self, lines: Vec<Vec<Span<'static>>>) -> Option<usize> {
+        let marks = std::mem::take(&mut self.marks);
+        let mut first = None;
+        for spans in lines {
+            // The columns of each non-space character.
+            let mut columns = Vec::new();
+            let mut column = 0;
+            for c in spans.iter().flat_map(|s| s.content.chars()) {
+                let width = c.width().unwrap_or(0);
+                if c != ' ' {
+                    columns.push((column, column + width));
+                }
+                column += width;
+            }
+            let start = self.laid_out;
+            self.laid_out += columns.len();
+            let line = self.push_line(spans);
+            first.get_or_insert(line);
+            let indent = self.lines[line].width() - column;
+
+            // A link whose text wrapped is only clickable on the line with
+            // its number.
+            for mark in marks
+                .iter()
+                .filter(|m| (start..self.laid_out).contains(&m.number))
+            {
+                let text = mark.text.saturating_sub(start);
+                let last = (mark.end - start).min(columns.len()) - 1;
+                self.link_areas.push(LinkArea {
+                    line,
+                    start: indent + columns[text].0,
+                    end: indent + columns[last].1,
+                    url: mark.url.clone(),
+                });
+            }
+        }
+        first
+    }
+
+    fn flush_pre(&mut self) {
+        if !self.inline.is_empty() {
+            self.flush_pre_line();
+        }
+    }
+
+    /// Adds a line after the current indents and returns its row.
+    fn push_line(&mut self, spans: Vec<Span<'static>>) -> usize {
+        if self.gap && !self.lines.is_empty() {
+            // Only the indents already started continue through the gap,
+            // so a quote's gutter does not stick out above it.
+            let prefix: Vec<Span> = self
+                .indents
+                .iter()
+                .filter(|i| i.used)
+                .map(|i| i.rest.clone())
+                .collect();
+            self.lines.push(Line::from(prefix));
+        }
+        self.gap = false;
+
+        let mut line = Vec::new();
+        for indent in &mut self.indents {
+            if indent.used {
+                line.push(indent.rest.clone());
+            } else {
+                line.push(indent.first.clone());
+                indent.used = true;
+            }
+        }
+        line.extend(spans);
+        self.lines.push(Line::from(line));
+        self.lines.len() - 1
+    }
+
+    fn table(&mut self, node: &Handle, style: Style) {
+        let mut rows = Vec::new();
+        collect_rows(node, &mut rows);
+        let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+        if columns == 0 {
+            return;
+        }
+
+        let cell_style = |header: bool| {
+            if header {
+                style.add_modifier(Modifier::BOLD)
+            } else {
+                style
+            }
+        };
+
+        // Start from each column's natural width and take from the widest
+        // until the table fits.
+        // Links in cells are numbered on from the ones before the table;
+        // measuring works on a copy so they are not counted twice.
+        let mut widths = vec![1; columns];
+        let mut measured = self.links.clone();
+        for row in &rows {
+            for (c, (cell, header)) in row.iter().enumerate() {
+                let natural = self
+                    .render_cell(cell, cell_style(*header), UNBOUNDED, &mut measured)
+                    .0
+                    .iter()
+                    .map(Line::width)
+                    .max()
+                    .unwrap_or(0);
+                widths[c] = widths[c].max(natural);
+            }
+        }
+        let budget = self
+            .available()
+            .saturating_sub(TABLE_SEPARATOR.width() * (columns - 1))
+            .max(columns);
+        while widths.iter().sum::<usize>() > budget {
+            let widest = (0..columns).max_by_key(|&c| widths[c]).unwrap_or(0);
+            widths[widest] -= 1;
+        }
+
+        for (r, row) in rows.iter().enumerate() {
+            let mut links = std::mem::take(&mut self.links);
+            let cells: Vec<(Vec<Line>, Vec<LinkArea>)> = row
+                .iter()
+                .enumerate()
+                .map(|(c, (cell, header))| {
+                    self.render_cell(cell, cell_style(*header), widths[c], &mut links)
+                })
+                .collect();
+            self.links = links;
+            let height = cells
+                .iter()
+                .map(|(lines, _)| lines.len())
+                .max()
+                .unwrap_or(0)
+                .max(1);
+
+            for i in 0..height {
+                let mut spans = Vec::new();
+                // Where each cell starts, for moving its links into place.
+                let mut starts = Vec::new();
+                let mut column = 0;
+                for (c, width) in widths.iter().enumerate() {
+                    if c > 0 {
+                        spans.push(Span::styled(TABLE_SEPARATOR, self.theme.gutter));
+                        column += TABLE_SEPARATOR.width();
                     }
+                    starts.push(column);
+                    column += width;
+                    let line = cells.get(c).and_then(|(lines, _)| lines.get(i));
+                    let used = line.map_or(0, Line::width);
+                    if let Some(line) = line {
+                        spans.extend(line.spans.iter().cloned());
+                    }
+                    spans.push(Span::raw(" ".repeat(width.saturating_sub(used))));
                 }
-                text
-            }
-            NodeData::Document => {
-                let mut text = Text::default();
-                for child in &node.children.clone().into_inner() {
-                    let child_text = dom_to_ratatui(child, parent_style);
-                    for line in child_text.lines {
-                        text.push_line(line)
+                let line = self.push_line(spans);
+                let indent = self.lines[line].width().saturating_sub(column);
+                for (c, (_, areas)) in cells.iter().enumerate() {
+                    for area in areas.iter().filter(|area| area.line == i) {
+                        self.link_areas.push(LinkArea {
+                            line,
+                            start: indent + starts[c] + area.start,
+                            end: indent + starts[c] + area.end,
+                            url: area.url.clone(),
+                        });
                     }
                 }
-                text
             }
-            _ => Text::raw("Could not read"),
+
+            if r == 0 && row.iter().all(|(_, header)| *header) {
+                let rule: Vec<String> = widths.iter().map(|w| "─".repeat(*w)).collect();
+                self.push_line(vec![Span::styled(rule.join("─┼─"), self.theme.gutter)]);
+            }
+        }
+    }
+
+    /// Renders a table cell on its own at `width`, numbering its links on
+    /// from `links`. Returns its lines and where its links are in them.
+    fn render_cell(
+        &self,
+        cell: &Handle,
+        style: Style,
+        width: usize,
+        links: &mut Vec<String>,
+    ) -> (Vec<Line<'static>>, Vec<LinkArea>) {
+        let mut renderer = Renderer::new(width, self.base.clone());
+        renderer.links = std::mem::take(links);
+        renderer.theme = self.theme;
+        renderer.children(cell, style);
+        renderer.flush();
+        *links = std::mem::take(&mut renderer.links);
+        (renderer.lines, renderer.link_areas)
+    }
+}
+
+const TABLE_SEPARATOR: &str = " │ ";
+
+fn heading_style(tag: &str, style: Style) -> Style {
+    match tag {
+        "h1" => style.add_modifier(Modifier::UNDERLINED),
+        "h2" => style,
+        _ => style.add_modifier(Modifier::ITALIC),
+    }
+}
+
+fn bullet(depth: usize) -> &'static str {
+    match depth {
+        0 | 1 => "•",
+        2 => "◦",
+        _ => "▪",
+    }
+}
+
+pub fn attr(node: &Handle, name: &str) -> Option<String> {
+    match &node.data {
+        NodeData::Element { attrs, .. } => attrs
+            .borrow()
+            .iter()
+            .find(|a| a.name.local.as_ref() == name)
+            .map(|a| a.value.to_string()),
+        _ => None,
+    }
+}
+
+/// All the text under `node`, as written.
+pub fn text_content(node: &Handle) -> String {
+    let mut text = String::new();
+    for child in node.children.borrow().iter() {
+        match &child.data {
+            NodeData::Text { contents } => text.push_str(&contents.borrow()),
+            NodeData::Element { .. } => text.push_str(&text_content(child)),
+            _ => {}
+        }
+    }
+    text
+}
+
+/// Language a `<pre>` block is marked up as, from its own attributes or
+/// those of the `<code>` inside it.
+fn code_language(pre: &Handle) -> Option<String> {
+    let code = pre
+        .children
+        .borrow()
+        .iter()
+        .find(|child| tag_name(child).as_deref() == Some("code"))
+        .cloned();
+    [Some(pre.clone()), code]
+        .into_iter()
+        .flatten()
+        .find_map(|node| {
+            attr(&node, "data-lang")
+                .or_else(|| attr(&node, "class").and_then(|c| highlight::language_from_class(&c)))
+        })
+}
+
+pub fn tag_name(node: &Handle) -> Option<String> {
+    match &node.data {
+        NodeData::Element { name, .. } => Some(name.local.to_string()),
+        _ => None,
+    }
+}
+
+/// Gathers the cells of each `<tr>` under `node`, with whether each is a
+/// `<th>`. Tables nested inside cells are left to the cell's own rendering.
+fn collect_rows(node: &Handle, rows: &mut Vec<Vec<(Handle, bool)>>) {
+    for child in node.children.borrow().iter() {
+        match tag_name(child).as_deref() {
+            Some("tr") => rows.push(
+                child
+                    .children
+                    .borrow()
+                    .iter()
+                    .filter_map(|cell| match tag_name(cell).as_deref() {
+                        Some("td") => Some((cell.clone(), false)),
+                        Some("th") => Some((cell.clone(), true)),
+                        _ => None,
+                    })
+                    .collect(),
+            ),
+            Some("thead" | "tbody" | "tfoot") => collect_rows(child, rows),
+            _ => {}
+        }
+    }
+}
+
+/// Greedy word wrap. Words are split at spaces only; a word longer than the
+/// line is broken across lines.
+fn wrap(spans: Vec<Span<'static>>, width: usize) -> Vec<Vec<Span<'static>>> {
+    let mut lines = Vec::new();
+    let mut line: Vec<Span<'static>> = Vec::new();
+    let mut line_width = 0;
+    let mut word: Vec<Span<'static>> = Vec::new();
+    let mut word_width = 0;
+
+    let mut place_word = |word: &mut Vec<Span<'static>>,
+                          word_width: &mut usize,
+                          line: &mut Vec<Span<'static>>,
+                          line_width: &mut usize| {
+        if *word_width == 0 {
+            return;
+        }
+        if *line_width > 0 && *line_width + 1 + *word_width > width {
+            lines.push(std::mem::take(line));
+            *line_width = 0;
+        }
+        if *line_width > 0 {
+            line.push(Span::raw(" "));
+            *line_width += 1;
+        }
+        if *word_width > width {
+            let mut broken = hard_wrap(std::mem::take(word), width);
+            let last = broken.pop().unwrap_or_default();
+            lines.extend(broken);
+            *line_width = last.iter().map(|s| s.width()).sum();
+            *line = last;
+        } else {
+            line.append(word);
+            *line_width += *word_width;
+        }
+        *word_width = 0;
+    };
+
+    for span in spans {
+        for (i, piece) in span.content.split(' ').enumerate() {
+            if i > 0 {
+                place_word(&mut word, &mut word_width, &mut line, &mut line_width);
+            }
+            if !piece.is_empty() {
+                word_width += piece.width();
+                word.push(Span::styled(piece.to_string(), span.style));
+            }
+        }
+    }
+    place_word(&mut word, &mut word_width, &mut line, &mut line_width);
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// The part of a line from column `offset` to `offset + width`.
+fn crop(spans: Vec<Span<'static>>, offset: usize, width: usize) -> Vec<Span<'static>> {
+    let mut cropped = Vec::new();
+    let mut column = 0;
+
+    for span in spans {
+        let mut piece = String::new();
+        for c in span.content.chars() {
+            let w = c.width().unwrap_or(0);
+            if column >= offset && column + w <= offset + width {
+                piece.push(c);
+            }
+            column += w;
+        }
+        if !piece.is_empty() {
+            cropped.push(Span::styled(piece, span.style));
+        }
+    }
+
+    cropped
+}
+
+/// Breaks spans into lines of at most `width` columns without regard to
+/// word boundaries.
+fn hard_wrap(spans: Vec<Span<'static>>, width: usize) -> Vec<Vec<Span<'static>>> {
+    let mut lines = Vec::new();
+    let mut line = Vec::new();
+    let mut line_width = 0;
+
+    for span in spans {
+        let mut piece = String::new();
+        for c in span.content.chars() {
+            let w = c.width().unwrap_or(0);
+            if line_width + w > width && line_width > 0 {
+                if !piece.is_empty() {
+                    line.push(Span::styled(std::mem::take(&mut piece), span.style));
+                }
+                lines.push(std::mem::take(&mut line));
+                line_width = 0;
+            }
+            piece.push(c);
+            line_width += w;
+        }
+        if !piece.is_empty() {
+            line.push(Span::styled(piece, span.style));
         }
     }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::theme::ThemeName;
+
+    /// The article's lines as plain text, without trailing spaces.
+    fn render_lines(html: &str, width: u16) -> Vec<String> {
+        let options = Options {
+            width,
+            theme: Theme::builtin(ThemeName::Terminal),
+            ..Default::default()
+        };
+        html_to_ratatui(html.as_bytes(), &options)
+            .text
+            .lines
+            .iter()
+            .map(|line| {
+                let text: String = line.spans.iter().map(|s| s.content.as_ref()).collect();
+                text.trim_end().to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn wraps_paragraphs_at_the_width() {
+        let html = "<p>one two three four five six seven eight nine ten</p><p>next</p>";
+        assert_eq!(
+            render_lines(html, 20),
+            [
+                "one two three four",
+                "five six seven eight",
+                "nine ten",
+                "",
+                "next"
+            ]
+        );
+    }
+
+    #[test]
+    fn indents_nested_lists_under_their_markers() {
+        let html = "<ul><li>first item that wraps around</li>\
+                    <li>second<ol start=\"3\"><li>nested</li><li>more</li></ol></li></ul>";
+        assert_eq!(
+            render_lines(html, 24),
+            [
+                "• first item that wraps",
+                "  around",
+                "• second",
+                "  3. nested",
+                "  4. more",
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_the_quote_gutter_on_wrapped_lines() {
+        let html = "<blockquote><p>quoted text that is long enough to wrap</p></blockquote>";
+        assert_eq!(
+            render_lines(html, 24),
+            ["│ quoted text that is", "│ long enough to wrap"]
+        );
+    }
 
-    dom_to_ratatui(&dom.document, Style::default().fg(Color::Red))
+    #[test]
+    fn expands_tabs_and_hard_wraps_pre_blocks() {
+        let html = "<pre>a\tb\n\tindented\nthis line is far too long for the reader</pre>";
+        assert_eq!(
+            render_lines(html, 20),
+            [
+                "a    b",
+                "    indented",
+                "this line is far too",
+                " long for the reader",
+            ]
+        );
+    }
+
+    #[test]
+    fn lines_up_table_columns() {
+        let html = "<table><tr><th>Name</th><th>Size</th></tr>\
+                    <tr><td>alpha</td><td>1</td></tr><tr><td>beta</td><td>22</td></tr></table>";
+        assert_eq!(
+            render_lines(html, 30),
+            ["Name  │ Size", "──────┼─────", "alpha │ 1", "beta  │ 22"]
+        );
+    }
+
+    #[test]
+    fn shows_a_placeholder_for_enclosures_without_images() {
+        let enclosures = ["https://example.com/a.png".to_string()];
+        let options = Options {
+            width: 30,
+            plain: true,
+            images: true,
+            enclosures: &enclosures,
+            ..Default::default()
+        };
+        let article = html_to_ratatui(b"<p>text</p>", &options);
+        assert!(article.images.is_empty());
+        assert_eq!(article.text.lines[0].to_string(), "[image]");
+    }
+
+    /// The text each link area covers, with its line and target.
+    fn link_texts(html: &str, width: u16) -> Vec<(usize, String, String)> {
+        let options = Options {
+            width,
+            theme: Theme::builtin(ThemeName::Terminal),
+            ..Default::default()
+        };
+        let article = html_to_ratatui(html.as_bytes(), &options);
+        article
+            .links
+            .iter()
+            .map(|area| {
+                let line = article.text.lines[area.line].to_string();
+                let text = line
+                    .chars()
+                    .skip(area.start)
+                    .take(area.end - area.start)
+                    .collect();
+                (area.line, text, area.url.clone())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn places_links_where_their_numbers_are() {
+        // The link text wraps, so only the part on the number's line is
+        // clickable; the literal `[3]` isn't a link.
+        let html = "<p>see <a href=\"https://a.example/\">the first link</a> and [3] \
+                    or <a href=\"https://b.example/\">b</a></p>\
+                    <ul><li><a href=\"https://a.example/\">again</a></li></ul>";
+        assert_eq!(
+            link_texts(html, 20),
+            [
+                (1, "link[1]".to_string(), "https://a.example/".to_string()),
+                (2, "b[2]".to_string(), "https://b.example/".to_string()),
+                (4, "again[1]".to_string(), "https://a.example/".to_string()),
+                (8, "[1]".to_string(), "https://a.example/".to_string()),
+                (10, "[2]".to_string(), "https://b.example/".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn places_links_in_table_cells() {
+        let html =
+            "<table><tr><td>name</td><td><a href=\"https://a.example/\">link</a></td></tr></table>";
+        assert_eq!(
+            link_texts(html, 30)[0],
+            (0, "link[1]".to_string(), "https://a.example/".to_string())
+        );
+    }
+
+    #[test]
+    fn lays_out_again_when_the_body_or_feed_changes() {
+        let options = Options::default();
+        let mut cache = None;
+        let mut first_line = |feed: &str, html: &str| {
+            let cached =
+                ArticleCache::get(&mut cache, feed, "post", Text::default(), html, &options);
+            cached.article.text.lines[0].to_string()
+        };
+        assert_eq!(first_line("1", "<p>old</p>"), "old");
+        assert_eq!(first_line("1", "<p>new</p>"), "new");
+        assert_eq!(first_line("2", "<p>other</p>"), "other");
+    }
+
+    #[test]
+    fn lists_link_targets_by_number() {
+        let html = "<table><tr><td><a href=\"https://b.example/\">b</a></td></tr></table>\
+                    <p><a href=\"/a\">a</a> <a href=\"https://b.example/\">b</a></p>";
+        let options = Options {
+            base: "https://site.example/post",
+            ..Default::default()
+        };
+        assert_eq!(
+            html_to_ratatui(html.as_bytes(), &options).targets,
+            ["https://b.example/", "https://site.example/a"]
+        );
+    }
 }
@@ -0,0 +1,305 @@
+use chrono::DateTime;
+use reqwest::Client;
+use serde::Deserialize;
+use std::{cmp::Reverse, collections::HashSet, error::Error};
+
+use crate::{
+    feed::Post,
+    sync::{self, Snapshot, Subscription, SyncConfig},
+};
+
+/// The server returns at most 50 items per request; this caps how many
+/// requests one pull makes.
+const MAX_PAGES: usize = 20;
+
+/// Client for the Fever API. It has no login step: every request carries
+/// the md5 of `username:password`.
+pub struct Fever {
+    http: Client,
+    url: String,
+    api_key: String,
+}
+
+#[derive(Deserialize)]
+struct Feeds {
+    feeds: Vec<FeedEntry>,
+}
+
+#[derive(Deserialize)]
+struct FeedEntry {
+    id: i64,
+    title: String,
+    #[serde(default)]
+    url: String,
+    #[serde(default)]
+    site_url: String,
+}
+
+#[derive(Deserialize)]
+struct Items {
+    #[serde(default)]
+    items: Vec<Item>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Item {
+    id: i64,
+    feed_id: i64,
+    title: String,
+    author: String,
+    html: String,
+    url: String,
+    created_on_time: i64,
+}
+
+#[derive(Deserialize)]
+struct Auth {
+    auth: i64,
+}
+
+impl Fever {
+    pub fn new(config: &SyncConfig) -> Fever {
+        let key = md5::compute(format!("{}:{}", config.username, config.password));
+        Fever {
+            http: sync::http(),
+            url: config.url.clone(),
+            api_key: format!("{key:x}"),
+        }
+    }
+
+    /// Calls the API with the given query arguments, after `api`.
+    async fn call(&self, args: &[(&str, &str)]) -> Result<String, Box<dyn Error>> {
+        let body = self
+            .http
+            .post(&self.url)
+            .query(&[("api", "")])
+            .query(args)
+            .form(&[("api_key", &self.api_key)])
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+
+        let auth: Auth = serde_json::from_str(&body)?;
+        if auth.auth != 1 {
+            return Err("Fever API rejected the username or password".into());
+        }
+        Ok(body)
+    }
+
+    /// Reads a comma-separated id list such as `unread_item_ids`.
+    async fn id_list(&self, name: &str) -> Result<HashSet<String>, Box<dyn Error>> {
+        let body: serde_json::Value = serde_json::from_str(&self.call(&[(name, "")]).await?)?;
+        Ok(body[name]
+            .as_str()
+            .unwrap_or_default()
+            .split(',')
+            .filter(|id| !id.is_empty())
+            .map(String::from)
+            .collect())
+    }
+
+    pub async fn pull(&self, known: &HashSet<String>) -> Result<Snapshot, Box<dyn Error>> {
+        let feeds: Feeds = serde_json::from_str(&self.call(&[("feeds", "")]).await?)?;
+        let subscriptions = feeds
+            .feeds
+            .into_iter()
+            .map(|f| Subscription {
+                id: f.id.to_string(),
+                title: f.title,
+                url: f.url,
+                site_url: f.site_url,
+                category: None,
+            })
+            .collect();
+
+        // Item ids only grow, so page forward from the newest one we have.
+        // The first pull has none and pages back from the newest item
+        // instead, so a large account starts with its recent items.
+        let newest_known = known.iter().filter_map(|id| id.parse::<i64>().ok()).max();
+        let mut cursor = newest_known.unwrap_or(i64::MAX);
+        let mut items = Vec::new();
+        for _ in 0..MAX_PAGES {
+            let position = cursor.to_string();
+            let page = match newest_known {
+                Some(_) => ("since_id", position.as_str()),
+                None => ("max_id", position.as_str()),
+            };
+            let page: Items = serde_json::from_str(&self.call(&[("items", ""), page]).await?)?;
+            let next = match newest_known {
+                Some(_) => page.items.iter().map(|i| i.id).max(),
+                None => page.items.iter().map(|i| i.id).min(),
+            };
+            let Some(next) = next else {
+                break;
+            };
+            cursor = next;
+            items.extend(page.items);
+        }
+        // Feeds keep the newest post at the top.
+        items.sort_by_key(|i| Reverse(i.id));
+        let items = items
+            .into_iter()
+            .map(|i| (i.feed_id.to_string(), to_post(i)))
+            .collect();
+
+        Ok(Snapshot {
+            subscriptions,
+            items,
+            unread: self.id_list("unread_item_ids").await?,
+            starred: self.id_list("saved_item_ids").await?,
+        })
+    }
+
+    pub async fn push(&self, posts: &[&Post]) -> Result<(), Box<dyn Error>> {
+        for post in posts {
+            let read = if post.read { "read" } else { "unread" };
+            let saved = if post.starred { "saved" } else { "unsaved" };
+            for state in [read, saved] {
+                self.call(&[("mark", "item"), ("as", state), ("id", &post.id)])
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn to_post(item: Item) -> Post {
+    Post {
+        id: item.id.to_string(),
+        title: item.title,
+        link: item.url,
+        authors: if item.author.is_empty() {
+            Vec::new()
+        } else {
+            vec![item.author]
+        },
+        published: DateTime::from_timestamp(item.created_on_time, 0).map(|d| d.fixed_offset()),
+        content: item.html,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::{
+        Api,
+        mock::{MockServer, Request},
+    };
+
+    fn config(url: &str) -> SyncConfig {
+        SyncConfig {
+            api: Api::Fever,
+            url: url.to_string(),
+            username: "me".to_string(),
+            password: "secret".to_string(),
+            token: None,
+        }
+    }
+
+    /// Items 1 to 120, served 50 at a time like a Fever server.
+    fn respond(request: &Request) -> (u16, String) {
+        let ids: Vec<i64> = if request.param("items").is_some() {
+            match (request.param("since_id"), request.param("max_id")) {
+                (Some(since), _) => {
+                    let since: i64 = since.parse().unwrap();
+                    (since + 1..=120).take(50).collect()
+                }
+                (_, Some(max)) => {
+                    let max: i64 = max.parse().unwrap();
+                    (1..max.min(121)).rev().take(50).collect()
+                }
+                _ => (1..=50).collect(),
+            }
+        } else {
+            Vec::new()
+        };
+        let items: Vec<String> = ids
+            .iter()
+            .map(|id| format!(r#"{{"id": {id}, "feed_id": 7, "title": "Item {id}"}}"#))
+            .collect();
+        let body = format!(
+            r#"{{"auth": 1, "feeds": [{{"id": 7, "title": "Seven"}}], "items": [{}],
+                "unread_item_ids": "118,119,120", "saved_item_ids": "5"}}"#,
+            items.join(",")
+        );
+        (200, body)
+    }
+
+    #[tokio::test]
+    async fn first_pull_starts_from_the_newest_items() {
+        let server = MockServer::start(respond).await;
+        let snapshot = Fever::new(&config(&server.url))
+            .pull(&HashSet::new())
+            .await
+            .unwrap();
+
+        assert_eq!(snapshot.subscriptions[0].id, "7");
+        assert_eq!(snapshot.items.len(), 120);
+        assert_eq!(snapshot.items[0].1.id, "120");
+        assert_eq!(snapshot.items[119].1.id, "1");
+        assert_eq!(
+            snapshot.unread,
+            HashSet::from(["118", "119", "120"].map(String::from))
+        );
+        assert_eq!(snapshot.starred, HashSet::from(["5".to_string()]));
+        assert!(
+            server
+                .requests()
+                .iter()
+                .all(|r| r.param("since_id").is_none())
+        );
+    }
+
+    #[tokio::test]
+    async fn later_pulls_page_forward_from_the_newest_known_item() {
+        let server = MockServer::start(respond).await;
+        let known = HashSet::from(["100".to_string(), "90".to_string()]);
+        let snapshot = Fever::new(&config(&server.url)).pull(&known).await.unwrap();
+
+        let ids: Vec<&str> = snapshot.items.iter().map(|(_, p)| p.id.as_str()).collect();
+        assert_eq!(
+            ids,
+            (101..=120).rev().map(|n| n.to_string()).collect::<Vec<_>>()
+        );
+        assert!(
+            server
+                .requests()
+                .iter()
+                .all(|r| r.param("max_id").is_none())
+        );
+    }
+
+    #[tokio::test]
+    async fn push_marks_each_item() {
+        let server = MockServer::start(respond).await;
+        let post = Post {
+            id: "42".to_string(),
+            read: true,
+            ..Default::default()
+        };
+        Fever::new(&config(&server.url))
+            .push(&[&post])
+            .await
+            .unwrap();
+
+        let requests = server.requests();
+        let marks: Vec<_> = requests
+            .iter()
+            .map(|r| (r.param("as"), r.param("id")))
+            .collect();
+        assert_eq!(
+            marks,
+            vec![(Some("read"), Some("42")), (Some("unsaved"), Some("42"))]
+        );
+        let key = format!("{:x}", md5::compute("me:secret"));
+        assert!(
+            requests
+                .iter()
+                .all(|r| r.param("api_key") == Some(key.as_str()))
+        );
+    }
+}
@@ -0,0 +1,414 @@
+use chrono::DateTime;
+use reqwest::{Client, RequestBuilder};
+use serde::Deserialize;
+use std::{collections::HashSet, error::Error};
+
+use crate::{
+    feed::Post,
+    sync::{self, Snapshot, Subscription, SyncConfig},
+};
+
+const READ: &str = "user/-/state/com.google/read";
+const STARRED: &str = "user/-/state/com.google/starred";
+const READING_LIST: &str = "user/-/state/com.google/reading-list";
+
+/// Page size when pulling items, and how many pages one pull may take.
+const PAGE_SIZE: usize = 100;
+const MAX_PAGES: usize = 10;
+/// Page size when listing item ids. Every page is read, however many.
+const ID_PAGE_SIZE: &str = "10000";
+
+/// Client for the Google Reader API as served by FreshRSS, Miniflux,
+/// Inoreader and others.
+pub struct GReader {
+    http: Client,
+    base: String,
+    auth: String,
+}
+
+#[derive(Deserialize)]
+struct SubscriptionList {
+    subscriptions: Vec<SubscriptionEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SubscriptionEntry {
+    id: String,
+    title: String,
+    #[serde(default)]
+    url: String,
+    #[serde(default)]
+    html_url: String,
+    #[serde(default)]
+    categories: Vec<Category>,
+}
+
+#[derive(Deserialize)]
+struct Category {
+    label: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ItemRefs {
+    #[serde(default)]
+    item_refs: Vec<ItemRef>,
+    continuation: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ItemRef {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct Stream {
+    #[serde(default)]
+    items: Vec<Item>,
+    continuation: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Item {
+    id: String,
+    title: String,
+    published: i64,
+    author: String,
+    alternate: Vec<Link>,
+    summary: Option<Content>,
+    content: Option<Content>,
+    origin: Origin,
+}
+
+#[derive(Deserialize, Default)]
+struct Link {
+    href: String,
+}
+
+#[derive(Deserialize, Default)]
+struct Content {
+    content: String,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct Origin {
+    stream_id: String,
+}
+
+impl GReader {
+    pub async fn login(config: &SyncConfig) -> Result<GReader, Box<dyn Error>> {
+        let http = sync::http();
+        let base = config.url.trim_end_matches('/').to_string();
+        let response = http
+            .post(format!("{base}/accounts/ClientLogin"))
+            .form(&[("Email", &config.username), ("Passwd", &config.password)])
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        let auth = response
+            .lines()
+            .find_map(|line| line.strip_prefix("Auth="))
+            .ok_or("Login response has no Auth token")?
+            .to_string();
+
+        Ok(GReader { http, base, auth })
+    }
+
+    fn get(&self, path: &str) -> RequestBuilder {
+        self.http
+            .get(format!("{}/reader/api/0/{}", self.base, path))
+            .header("Authorization", format!("GoogleLogin auth={}", self.auth))
+    }
+
+    fn post(&self, path: &str) -> RequestBuilder {
+        self.http
+            .post(format!("{}/reader/api/0/{}", self.base, path))
+            .header("Authorization", format!("GoogleLogin auth={}", self.auth))
+    }
+
+    async fn get_json<T: for<'de> Deserialize<'de>>(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<T, Box<dyn Error>> {
+        let body = self
+            .get(path)
+            .query(&[("output", "json")])
+            .query(query)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    /// Ids of every item in `stream`, excluding those in `exclude`. The
+    /// list is paged, so follow the continuation to the end: an unread id
+    /// left out would mark its post read.
+    async fn item_ids(
+        &self,
+        stream: &str,
+        exclude: Option<&str>,
+    ) -> Result<HashSet<String>, Box<dyn Error>> {
+        let mut ids = HashSet::new();
+        let mut continuation: Option<String> = None;
+        loop {
+            let mut query = vec![("s", stream), ("n", ID_PAGE_SIZE)];
+            if let Some(exclude) = exclude {
+                query.push(("xt", exclude));
+            }
+            if let Some(c) = &continuation {
+                query.push(("c", c.as_str()));
+            }
+            let refs: ItemRefs = self.get_json("stream/items/ids", &query).await?;
+            ids.extend(refs.item_refs.iter().map(|r| long_id(&r.id)));
+            match refs.continuation {
+                Some(c) if !c.is_empty() && !refs.item_refs.is_empty() => continuation = Some(c),
+                _ => return Ok(ids),
+            }
+        }
+    }
+
+    pub async fn pull(&self, known: &HashSet<String>) -> Result<Snapshot, Box<dyn Error>> {
+        let list: SubscriptionList = self.get_json("subscription/list", &[]).await?;
+        let subscriptions = list
+            .subscriptions
+            .into_iter()
+            .map(|s| Subscription {
+                id: s.id,
+                title: s.title,
+                url: s.url,
+                site_url: s.html_url,
+                category: s.categories.into_iter().next().map(|c| c.label),
+            })
+            .collect();
+
+        // Items come newest first, so stop at the first page that reaches
+        // items from an earlier pull.
+        let mut items = Vec::new();
+        let mut continuation: Option<String> = None;
+        for _ in 0..MAX_PAGES {
+            let page_size = PAGE_SIZE.to_string();
+            let mut query = vec![("n", page_size.as_str())];
+            if let Some(c) = &continuation {
+                query.push(("c", c.as_str()));
+            }
+            let stream: Stream = self
+                .get_json(&format!("stream/contents/{READING_LIST}"), &query)
+                .await?;
+
+            let reached_known = stream.items.iter().any(|i| known.contains(&i.id));
+            items.extend(
+                stream
+                    .items
+                    .into_iter()
+                    .filter(|i| !known.contains(&i.id))
+                    .map(|i| (i.origin.stream_id.clone(), to_post(i))),
+            );
+            continuation = stream.continuation;
+            if reached_known || continuation.is_none() {
+                break;
+            }
+        }
+
+        Ok(Snapshot {
+            subscriptions,
+            items,
+            unread: self.item_ids(READING_LIST, Some(READ)).await?,
+            starred: self.item_ids(STARRED, None).await?,
+        })
+    }
+
+    pub async fn push(&self, posts: &[&Post]) -> Result<(), Box<dyn Error>> {
+        if posts.is_empty() {
+            return Ok(());
+        }
+
+        let token = self
+            .get("token")
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+
+        let edits = [
+            ("a", READ, true),
+            ("r", READ, false),
+            ("a", STARRED, true),
+            ("r", STARRED, false),
+        ];
+        for (action, tag, value) in edits {
+            let flag = |p: &Post| if tag == READ { p.read } else { p.starred };
+            let mut form = vec![("T", token.trim()), (action, tag)];
+            form.extend(
+                posts
+                    .iter()
+                    .filter(|p| flag(p) == value)
+                    .map(|p| ("i", p.id.as_str())),
+            );
+            if form.len() == 2 {
+                continue;
+            }
+            self.post("edit-tag")
+                .form(&form)
+                .send()
+                .await?
+                .error_for_status()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The ids endpoint returns item ids as decimal numbers, while the contents
+/// endpoint uses the long hexadecimal form.
+fn long_id(id: &str) -> String {
+    match id.parse::<i64>() {
+        Ok(n) => format!("tag:google.com,2005:reader/item/{:016x}", n),
+        Err(_) => id.to_string(),
+    }
+}
+
+fn to_post(item: Item) -> Post {
+    Post {
+        id: item.id,
+        title: item.title,
+        link: item
+            .alternate
+            .into_iter()
+            .next()
+            .map(|l| l.href)
+            .unwrap_or_default(),
+        authors: if item.author.is_empty() {
+            Vec::new()
+        } else {
+            vec![item.author]
+        },
+        published: DateTime::from_timestamp(item.published, 0).map(|d| d.fixed_offset()),
+        content: item
+            .content
+            .or(item.summary)
+            .map(|c| c.content)
+            .unwrap_or_default(),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::{
+        Api,
+        mock::{MockServer, Request},
+    };
+
+    fn config(url: &str) -> SyncConfig {
+        SyncConfig {
+            api: Api::GReader,
+            url: url.to_string(),
+            username: "me".to_string(),
+            password: "secret".to_string(),
+            token: None,
+        }
+    }
+
+    fn respond(request: &Request) -> (u16, String) {
+        let path = request.path.trim_start_matches("/reader/api/0/");
+        let body = match path {
+            "/accounts/ClientLogin" => "SID=x\nAuth=abc\n".to_string(),
+            "token" => "tok\n".to_string(),
+            "edit-tag" => "OK".to_string(),
+            "subscription/list" => r#"{"subscriptions": [
+                {"id": "feed/1", "title": "One", "url": "https://one.example/rss",
+                 "htmlUrl": "https://one.example", "categories": [{"label": "News"}]}
+            ]}"#
+            .to_string(),
+            "stream/items/ids" => match (request.param("s"), request.param("c")) {
+                (Some(STARRED), _) => r#"{"itemRefs": [{"id": "3"}]}"#.to_string(),
+                (_, None) => r#"{"itemRefs": [{"id": "1"}], "continuation": "page2"}"#.to_string(),
+                (_, Some("page2")) => r#"{"itemRefs": [{"id": "2"}]}"#.to_string(),
+                _ => return (400, String::new()),
+            },
+            p if p.starts_with("stream/contents/") => r#"{"items": [
+                {"id": "tag:google.com,2005:reader/item/0000000000000002", "title": "New",
+                 "published": 1700000000, "alternate": [{"href": "https://one.example/2"}],
+                 "content": {"content": "<p>body</p>"}, "origin": {"streamId": "feed/1"}},
+                {"id": "tag:google.com,2005:reader/item/0000000000000001", "title": "Old",
+                 "origin": {"streamId": "feed/1"}}
+            ]}"#
+            .to_string(),
+            _ => return (404, String::new()),
+        };
+        (200, body)
+    }
+
+    #[tokio::test]
+    async fn pull_follows_id_continuations() {
+        let server = MockServer::start(respond).await;
+        let client = GReader::login(&config(&server.url)).await.unwrap();
+        let known = HashSet::from(["tag:google.com,2005:reader/item/0000000000000001".to_string()]);
+        let snapshot = client.pull(&known).await.unwrap();
+
+        assert_eq!(snapshot.subscriptions.len(), 1);
+        assert_eq!(snapshot.subscriptions[0].category.as_deref(), Some("News"));
+        assert_eq!(snapshot.items.len(), 1);
+        assert_eq!(snapshot.items[0].0, "feed/1");
+        assert_eq!(snapshot.items[0].1.title, "New");
+        assert_eq!(snapshot.items[0].1.link, "https://one.example/2");
+
+        let unread: HashSet<String> = [1, 2].iter().map(|n| long_id(&n.to_string())).collect();
+        assert_eq!(snapshot.unread, unread);
+        assert_eq!(snapshot.starred, HashSet::from([long_id("3")]));
+        assert!(
+            server
+                .requests()
+                .iter()
+                .all(|r| r.path.ends_with("ClientLogin") || r.param("output") == Some("json"))
+        );
+    }
+
+    #[tokio::test]
+    async fn push_tags_items_by_flag() {
+        let server = MockServer::start(respond).await;
+        let client = GReader::login(&config(&server.url)).await.unwrap();
+        let read = Post {
+            id: "a".to_string(),
+            read: true,
+            starred: true,
+            ..Default::default()
+        };
+        let unread = Post {
+            id: "b".to_string(),
+            ..Default::default()
+        };
+        client.push(&[&read, &unread]).await.unwrap();
+
+        let edits: Vec<Request> = server
+            .requests()
+            .into_iter()
+            .filter(|r| r.path.ends_with("edit-tag"))
+            .collect();
+        let edit = |action: &str, tag: &str| {
+            edits
+                .iter()
+                .find(|r| r.param(action) == Some(tag))
+                .map(|r| r.params("i"))
+        };
+        assert!(
+            edits
+                .iter()
+                .all(|r| r.method == "POST" && r.param("T") == Some("tok"))
+        );
+        assert_eq!(edit("a", READ), Some(vec!["a"]));
+        assert_eq!(edit("r", READ), Some(vec!["b"]));
+        assert_eq!(edit("a", STARRED), Some(vec!["a"]));
+        assert_eq!(edit("r", STARRED), Some(vec!["b"]));
+    }
+}
@@ -0,0 +1,371 @@
+use chrono::{DateTime, FixedOffset};
+use reqwest::{Client, Method, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use std::{cmp::Reverse, collections::HashSet, error::Error};
+
+use crate::{
+    feed::Post,
+    sync::{self, Snapshot, Subscription, SyncConfig},
+};
+
+/// Entries per request, and how many requests one pull may take.
+const PAGE_SIZE: usize = 100;
+const MAX_PAGES: usize = 10;
+/// Entries per request when listing ids.
+const ID_PAGE_SIZE: usize = 10000;
+
+/// Client for the Miniflux REST API (`/v1`). Authenticates with an API
+/// token if one is configured, otherwise with the username and password.
+pub struct Miniflux {
+    http: Client,
+    base: String,
+    token: Option<String>,
+    username: String,
+    password: String,
+}
+
+#[derive(Deserialize)]
+struct FeedEntry {
+    id: i64,
+    title: String,
+    feed_url: String,
+    site_url: String,
+    category: Option<Category>,
+}
+
+#[derive(Deserialize)]
+struct Category {
+    title: String,
+}
+
+#[derive(Deserialize)]
+struct Entries {
+    entries: Vec<Entry>,
+}
+
+#[derive(Deserialize)]
+struct Entry {
+    id: i64,
+    feed_id: i64,
+    title: String,
+    url: String,
+    #[serde(default)]
+    author: String,
+    #[serde(default)]
+    content: String,
+    published_at: Option<DateTime<FixedOffset>>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    enclosures: Option<Vec<Enclosure>>,
+}
+
+#[derive(Deserialize)]
+struct Enclosure {
+    url: String,
+    mime_type: String,
+}
+
+#[derive(Serialize)]
+struct StatusUpdate<'a> {
+    entry_ids: Vec<i64>,
+    status: &'a str,
+}
+
+impl Miniflux {
+    pub fn new(config: &SyncConfig) -> Miniflux {
+        Miniflux {
+            http: sync::http(),
+            base: config.url.trim_end_matches('/').to_string(),
+            token: config.token.clone(),
+            username: config.username.clone(),
+            password: config.password.clone(),
+        }
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let request = self
+            .http
+            .request(method, format!("{}/v1/{}", self.base, path));
+        match &self.token {
+            Some(token) => request.header("X-Auth-Token", token),
+            None => request.basic_auth(&self.username, Some(&self.password)),
+        }
+    }
+
+    async fn get_json<T: for<'de> Deserialize<'de>>(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<T, Box<dyn Error>> {
+        let body = self
+            .request(Method::GET, path)
+            .query(query)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    async fn put(&self, path: &str, body: Option<String>) -> Result<(), Box<dyn Error>> {
+        let mut request = self.request(Method::PUT, path);
+        if let Some(body) = body {
+            request = request
+                .header("Content-Type", "application/json")
+                .body(body);
+        }
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+
+    /// Ids of every entry matching `query`, read page by page to the end.
+    async fn entry_ids(&self, query: &[(&str, &str)]) -> Result<HashSet<String>, Box<dyn Error>> {
+        let mut ids = HashSet::new();
+        let limit = ID_PAGE_SIZE.to_string();
+        let mut offset = 0;
+        loop {
+            let offset_param = offset.to_string();
+            let mut page_query = query.to_vec();
+            page_query.extend([("limit", limit.as_str()), ("offset", offset_param.as_str())]);
+            let entries: Entries = self.get_json("entries", &page_query).await?;
+            let count = entries.entries.len();
+            ids.extend(entries.entries.iter().map(|e| e.id.to_string()));
+            offset += count;
+            if count < ID_PAGE_SIZE {
+                return Ok(ids);
+            }
+        }
+    }
+
+    /// Asks the server to refresh its feeds, then pulls. The server refreshes
+    /// in the background, so its new entries may only arrive with the next
+    /// pull.
+    pub async fn pull(&self, known: &HashSet<String>) -> Result<Snapshot, Box<dyn Error>> {
+        self.put("feeds/refresh", None).await?;
+
+        let feeds: Vec<FeedEntry> = self.get_json("feeds", &[]).await?;
+        let subscriptions = feeds
+            .into_iter()
+            .map(|f| Subscription {
+                id: f.id.to_string(),
+                title: f.title,
+                url: f.feed_url,
+                site_url: f.site_url,
+                category: f.category.map(|c| c.title),
+            })
+            .collect();
+
+        // Entry ids only grow, so page forward from the newest one we have.
+        // The first pull has none and pages back from the newest entry
+        // instead, so a large account starts with its recent entries.
+        let newest_known = known.iter().filter_map(|id| id.parse::<i64>().ok()).max();
+        let mut cursor = newest_known;
+        let mut items = Vec::new();
+        for _ in 0..MAX_PAGES {
+            let position = cursor.map(|id| id.to_string());
+            let limit = PAGE_SIZE.to_string();
+            let mut query = vec![("order", "id"), ("limit", limit.as_str())];
+            match (newest_known, &position) {
+                (Some(_), Some(after)) => {
+                    query.extend([("after_entry_id", after.as_str()), ("direction", "asc")])
+                }
+                (None, Some(before)) => {
+                    query.extend([("before_entry_id", before.as_str()), ("direction", "desc")])
+                }
+                (_, None) => query.push(("direction", "desc")),
+            }
+            let page: Entries = self.get_json("entries", &query).await?;
+            let next = match newest_known {
+                Some(_) => page.entries.iter().map(|e| e.id).max(),
+                None => page.entries.iter().map(|e| e.id).min(),
+            };
+            if next.is_none() {
+                break;
+            }
+            cursor = next;
+            items.extend(page.entries);
+        }
+        // Feeds keep the newest post at the top.
+        items.sort_by_key(|e| Reverse(e.id));
+        let items = items
+            .into_iter()
+            .map(|e| (e.feed_id.to_string(), to_post(e)))
+            .collect();
+
+        Ok(Snapshot {
+            subscriptions,
+            items,
+            unread: self.entry_ids(&[("status", "unread")]).await?,
+            starred: self.entry_ids(&[("starred", "true")]).await?,
+        })
+    }
+
+    pub async fn push(&self, posts: &[&Post]) -> Result<(), Box<dyn Error>> {
+        if posts.is_empty() {
+            return Ok(());
+        }
+
+        for (status, read) in [("read", true), ("unread", false)] {
+            let entry_ids: Vec<i64> = posts
+                .iter()
+                .filter(|p| p.read == read)
+                .filter_map(|p| p.id.parse().ok())
+                .collect();
+            if !entry_ids.is_empty() {
+                let update = StatusUpdate { entry_ids, status };
+                self.put("entries", Some(serde_json::to_string(&update)?))
+                    .await?;
+            }
+        }
+
+        // The bookmark endpoint toggles, so only touch entries whose star
+        // changed since the last pull. Posts never synced are left alone.
+        for post in posts {
+            if post
+                .server_starred
+                .is_some_and(|starred| starred != post.starred)
+            {
+                self.put(&format!("entries/{}/bookmark", post.id), None)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn to_post(entry: Entry) -> Post {
+    Post {
+        id: entry.id.to_string(),
+        title: entry.title,
+        link: entry.url,
+        authors: if entry.author.is_empty() {
+            Vec::new()
+        } else {
+            vec![entry.author]
+        },
+        categories: entry.tags,
+        published: entry.published_at,
+        content: entry.content,
+        images: entry
+            .enclosures
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|e| e.mime_type.starts_with("image/"))
+            .map(|e| e.url)
+            .collect(),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::{
+        Api,
+        mock::{MockServer, Request},
+    };
+
+    fn config(url: &str) -> SyncConfig {
+        SyncConfig {
+            api: Api::Miniflux,
+            url: url.to_string(),
+            username: String::new(),
+            password: String::new(),
+            token: Some("token".to_string()),
+        }
+    }
+
+    fn entry(id: i64) -> String {
+        format!(r#"{{"id": {id}, "feed_id": 3, "title": "Entry {id}", "url": "https://e/{id}"}}"#)
+    }
+
+    /// Entries 1 to 250, newest first unless asked otherwise.
+    fn respond(request: &Request) -> (u16, String) {
+        let body = match (request.method.as_str(), request.path.as_str()) {
+            ("PUT", _) => String::new(),
+            (_, "/v1/feeds") => r#"[{"id": 3, "title": "Three", "feed_url": "https://three/rss",
+                     "site_url": "https://three", "category": {"title": "Misc"}}]"#
+                .to_string(),
+            (_, "/v1/entries") => {
+                let param = |name| request.param(name).and_then(|v| v.parse::<i64>().ok());
+                let limit = param("limit").unwrap_or(100) as usize;
+                let ids: Vec<i64> = if request.param("status") == Some("unread") {
+                    (1..=250)
+                        .skip(param("offset").unwrap_or(0) as usize)
+                        .take(limit)
+                        .collect()
+                } else if request.param("starred").is_some() {
+                    vec![7]
+                } else if let Some(after) = param("after_entry_id") {
+                    (after + 1..=250).take(limit).collect()
+                } else {
+                    let before = param("before_entry_id").unwrap_or(251);
+                    (1..before).rev().take(limit).collect()
+                };
+                let entries: Vec<String> = ids.into_iter().map(entry).collect();
+                format!(r#"{{"total": 250, "entries": [{}]}}"#, entries.join(","))
+            }
+            _ => return (404, String::new()),
+        };
+        (200, body)
+    }
+
+    #[tokio::test]
+    async fn first_pull_starts_from_the_newest_entries() {
+        let server = MockServer::start(respond).await;
+        let snapshot = Miniflux::new(&config(&server.url))
+            .pull(&HashSet::new())
+            .await
+            .unwrap();
+
+        assert_eq!(snapshot.subscriptions[0].category.as_deref(), Some("Misc"));
+        assert_eq!(snapshot.items.len(), 250);
+        assert_eq!(snapshot.items[0].1.id, "250");
+        assert_eq!(snapshot.unread.len(), 250);
+        assert_eq!(snapshot.starred, HashSet::from(["7".to_string()]));
+        let requests = server.requests();
+        assert!(requests.iter().all(|r| r.param("after_entry_id").is_none()));
+        assert!(requests.iter().any(|r| r.path == "/v1/feeds/refresh"));
+    }
+
+    #[tokio::test]
+    async fn later_pulls_page_forward_from_the_newest_known_entry() {
+        let server = MockServer::start(respond).await;
+        let known = HashSet::from(["240".to_string()]);
+        let snapshot = Miniflux::new(&config(&server.url))
+            .pull(&known)
+            .await
+            .unwrap();
+
+        let ids: Vec<&str> = snapshot.items.iter().map(|(_, p)| p.id.as_str()).collect();
+        assert_eq!(
+            ids,
+            (241..=250).rev().map(|n| n.to_string()).collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn push_toggles_only_changed_stars() {
+        let server = MockServer::start(respond).await;
+        let post = |id: &str, starred, server_starred| Post {
+            id: id.to_string(),
+            read: true,
+            starred,
+            server_starred,
+            ..Default::default()
+        };
+        let changed = post("1", true, Some(false));
+        let unchanged = post("2", true, Some(true));
+        let never_synced = post("3", false, None);
+        Miniflux::new(&config(&server.url))
+            .push(&[&changed, &unchanged, &never_synced])
+            .await
+            .unwrap();
+
+        let paths: Vec<String> = server.requests().into_iter().map(|r| r.path).collect();
+        assert_eq!(paths, vec!["/v1/entries", "/v1/entries/1/bookmark"]);
+    }
+}
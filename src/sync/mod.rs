@@ -0,0 +1,105 @@
+use serde::Deserialize;
+use std::{collections::HashSet, error::Error, time::Duration};
+
+use crate::feed::Post;
+
+pub mod fever;
+pub mod greader;
+pub mod miniflux;
+#[cfg(test)]
+pub mod mock;
+
+/// A self-hosted aggregator (FreshRSS, Miniflux, ...) to mirror:
+///
+/// ```toml
+/// [sync]
+/// api = "greader"
+/// url = "https://rss.example.com/api/greader.php"
+/// username = "me"
+/// password = "api password"
+/// ```
+#[derive(Deserialize, Clone)]
+pub struct SyncConfig {
+    pub api: Api,
+    pub url: String,
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub password: String,
+    /// API token, used instead of the username and password by Miniflux.
+    pub token: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Api {
+    GReader,
+    Fever,
+    Miniflux,
+}
+
+/// A feed the server is subscribed to.
+pub struct Subscription {
+    pub id: String,
+    pub title: String,
+    /// URL of the feed itself.
+    pub url: String,
+    pub site_url: String,
+    pub category: Option<String>,
+}
+
+/// What one pull brings back from the server.
+pub struct Snapshot {
+    pub subscriptions: Vec<Subscription>,
+    /// Items russ did not know yet, each with its subscription id.
+    pub items: Vec<(String, Post)>,
+    /// Ids of every unread and every starred item on the server. Together
+    /// they settle the state of all posts, not only the new ones.
+    pub unread: HashSet<String>,
+    pub starred: HashSet<String>,
+}
+
+pub enum Client {
+    GReader(greader::GReader),
+    Fever(fever::Fever),
+    Miniflux(miniflux::Miniflux),
+}
+
+impl Client {
+    /// Logs in to the server described by `config`.
+    pub async fn connect(config: &SyncConfig) -> Result<Client, Box<dyn Error>> {
+        Ok(match config.api {
+            Api::GReader => Client::GReader(greader::GReader::login(config).await?),
+            Api::Fever => Client::Fever(fever::Fever::new(config)),
+            Api::Miniflux => Client::Miniflux(miniflux::Miniflux::new(config)),
+        })
+    }
+
+    /// Fetches the subscriptions, the items not in `known` and the current
+    /// unread/starred state.
+    pub async fn pull(&self, known: &HashSet<String>) -> Result<Snapshot, Box<dyn Error>> {
+        match self {
+            Client::GReader(client) => client.pull(known).await,
+            Client::Fever(client) => client.pull(known).await,
+            Client::Miniflux(client) => client.pull(known).await,
+        }
+    }
+
+    /// Sends the read and starred flags of `posts` to the server.
+    pub async fn push(&self, posts: &[&Post]) -> Result<(), Box<dyn Error>> {
+        match self {
+            Client::GReader(client) => client.push(posts).await,
+            Client::Fever(client) => client.push(posts).await,
+            Client::Miniflux(client) => client.push(posts).await,
+        }
+    }
+}
+
+/// HTTP client shared by the backends. A refresh from the TUI waits on the
+/// sync, so requests give up rather than hang on an unreachable server.
+fn http() -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .unwrap_or_default()
+}
@@ -0,0 +1,359 @@
+use ratatui::style::{Color, Modifier, Style};
+use serde::Deserialize;
+use std::{collections::HashMap, env, error::Error, str::FromStr};
+
+/// Built-in themes a config can start from.
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ThemeName {
+    Dark,
+    Light,
+    Solarized,
+    /// The terminal's own colours; only bold, underline and the like.
+    Terminal,
+}
+
+impl ThemeName {
+    /// `terminal` when `NO_COLOR` is set, as https://no-color.org asks,
+    /// and `dark` otherwise.
+    pub fn from_env() -> ThemeName {
+        if env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+            ThemeName::Terminal
+        } else {
+            ThemeName::Dark
+        }
+    }
+}
+
+/// The `[theme]` table as written in the config:
+///
+/// ```toml
+/// [theme]
+/// name = "light"
+/// syntax = "InspiredGitHub"
+/// link = { fg = "blue", modifiers = ["underlined"] }
+/// selected = { bg = "#eee8d5" }
+/// ```
+#[derive(Deserialize, Default)]
+pub struct ThemeConfig {
+    /// Defaults to `dark`, or `terminal` when `NO_COLOR` is set.
+    pub name: Option<ThemeName>,
+    /// Syntect theme for code blocks.
+    pub syntax: Option<String>,
+    /// Overrides for single elements, by field name of `Theme`.
+    #[serde(flatten)]
+    pub styles: HashMap<String, StyleConfig>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StyleConfig {
+    pub fg: Option<String>,
+    pub bg: Option<String>,
+    /// Replaces the element's modifiers when given.
+    pub modifiers: Option<Vec<String>>,
+}
+
+/// Syntax highlighting themes bundled with syntect.
+pub const SYNTAX_THEMES: &[&str] = &[
+    "base16-ocean.dark",
+    "base16-eighties.dark",
+    "base16-mocha.dark",
+    "base16-ocean.light",
+    "InspiredGitHub",
+    "Solarized (dark)",
+    "Solarized (light)",
+];
+
+/// Styles for everything russ draws. The HTML styles are patched onto the
+/// style of the surrounding text, so a colour left unset is inherited.
+#[derive(Clone, Copy, Hash)]
+pub struct Theme {
+    /// Feed titles in the main menu.
+    pub feed: Style,
+    /// Smart, category and tag folders in the main menu.
+    pub folder: Style,
+    /// Post titles.
+    pub post: Style,
+    /// Patched onto unread post titles.
+    pub unread: Style,
+    /// The star marker of starred posts.
+    pub star: Style,
+    /// Tags after post titles and in the reader header.
+    pub tag: Style,
+    /// Prompt and reader header labels.
+    pub label: Style,
+    /// The selected feed or folder.
+    pub feed_selected: Style,
+    /// The selected post or link.
+    pub selected: Style,
+    /// Borders and titles of the reader and popups.
+    pub border: Style,
+    /// The status bar at the bottom.
+    pub status: Style,
+    /// Errors in the status bar and the message log.
+    pub error: Style,
+    /// Article text.
+    pub text: Style,
+    pub heading: Style,
+    pub link: Style,
+    /// Link numbers after link text, in the references and the link picker.
+    pub link_number: Style,
+    /// Inline code and code blocks that aren't highlighted.
+    pub code: Style,
+    pub quote: Style,
+    pub mark: Style,
+    /// Quote bars, list markers, rules, table lines and alt text.
+    pub gutter: Style,
+    /// Syntect theme for code blocks; `None` leaves them unhighlighted.
+    pub syntax: Option<&'static str>,
+    /// Gives the theme's shade of a rule's highlight colour; `None` drops
+    /// highlights, for themes without colours.
+    pub highlights: Option<fn(Color) -> Color>,
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme::dark()
+    }
+}
+
+impl Theme {
+    /// The configured theme: a built-in one with the config's overrides.
+    pub fn from_config(config: &ThemeConfig) -> Result<Theme, Box<dyn Error>> {
+        let mut theme = Theme::builtin(config.name.unwrap_or_else(ThemeName::from_env));
+
+        if let Some(syntax) = &config.syntax {
+            theme.syntax = Some(
+                SYNTAX_THEMES
+                    .iter()
+                    .find(|name| *name == syntax)
+                    .ok_or(format!("Unknown syntax theme: {syntax}"))?,
+            );
+        }
+        for (name, style) in &config.styles {
+            let target = theme
+                .style_mut(name)
+                .ok_or(format!("Unknown theme element: {name}"))?;
+            *target = style.apply(*target)?;
+        }
+        Ok(theme)
+    }
+
+    pub fn builtin(name: ThemeName) -> Theme {
+        match name {
+            ThemeName::Dark => Theme::dark(),
+            ThemeName::Light => Theme::light(),
+            ThemeName::Solarized => Theme::solarized(),
+            ThemeName::Terminal => Theme::terminal(),
+        }
+    }
+
+    fn dark() -> Theme {
+        Theme {
+            feed: Style::new().fg(Color::Yellow),
+            folder: Style::new().fg(Color::Cyan),
+            post: Style::new().fg(Color::Green),
+            unread: Style::new().add_modifier(Modifier::BOLD),
+            star: Style::new().fg(Color::Yellow),
+            tag: Style::new().fg(Color::Cyan),
+            label: Style::new().fg(Color::Yellow),
+            feed_selected: Style::new().bg(Color::Green).add_modifier(Modifier::BOLD),
+            selected: Style::new().bg(Color::Red).add_modifier(Modifier::BOLD),
+            border: Style::new(),
+            status: Style::new().fg(Color::White).bg(Color::DarkGray),
+            error: Style::new()
+                .fg(Color::LightRed)
+                .add_modifier(Modifier::BOLD),
+            text: Style::new().fg(Color::Gray),
+            heading: Style::new().fg(Color::White).add_modifier(Modifier::BOLD),
+            link: Style::new()
+                .fg(Color::Blue)
+                .add_modifier(Modifier::UNDERLINED),
+            link_number: Style::new().fg(Color::Blue),
+            code: Style::new().fg(Color::Yellow),
+            quote: Style::new().add_modifier(Modifier::ITALIC),
+            mark: Style::new().add_modifier(Modifier::REVERSED),
+            gutter: Style::new().fg(Color::DarkGray),
+            syntax: Some("base16-ocean.dark"),
+            highlights: Some(|color| color),
+        }
+    }
+
+    fn light() -> Theme {
+        Theme {
+            feed: Style::new().fg(Color::Blue),
+            folder: Style::new().fg(Color::Magenta),
+            post: Style::new().fg(Color::Black),
+            unread: Style::new().add_modifier(Modifier::BOLD),
+            star: Style::new().fg(Color::Red),
+            tag: Style::new().fg(Color::Magenta),
+            label: Style::new().fg(Color::Blue),
+            feed_selected: Style::new()
+                .bg(Color::LightBlue)
+                .add_modifier(Modifier::BOLD),
+            selected: Style::new()
+                .bg(Color::LightYellow)
+                .add_modifier(Modifier::BOLD),
+            border: Style::new().fg(Color::DarkGray),
+            status: Style::new().fg(Color::Black).bg(Color::Gray),
+            error: Style::new().fg(Color::Red).add_modifier(Modifier::BOLD),
+            text: Style::new().fg(Color::Black),
+            heading: Style::new().add_modifier(Modifier::BOLD),
+            link: Style::new()
+                .fg(Color::Blue)
+                .add_modifier(Modifier::UNDERLINED),
+            link_number: Style::new().fg(Color::Blue),
+            code: Style::new().fg(Color::Red),
+            quote: Style::new().add_modifier(Modifier::ITALIC),
+            mark: Style::new().bg(Color::LightYellow),
+            gutter: Style::new().fg(Color::Gray),
+            syntax: Some("InspiredGitHub"),
+            highlights: Some(|color| color),
+        }
+    }
+
+    fn solarized() -> Theme {
+        const BASE02: Color = Color::Rgb(0x07, 0x36, 0x42);
+        const BASE01: Color = Color::Rgb(0x58, 0x6e, 0x75);
+        const BASE0: Color = Color::Rgb(0x83, 0x94, 0x96);
+        const BASE1: Color = Color::Rgb(0x93, 0xa1, 0xa1);
+        const YELLOW: Color = Color::Rgb(0xb5, 0x89, 0x00);
+        const ORANGE: Color = Color::Rgb(0xcb, 0x4b, 0x16);
+        const VIOLET: Color = Color::Rgb(0x6c, 0x71, 0xc4);
+        const BLUE: Color = Color::Rgb(0x26, 0x8b, 0xd2);
+        const CYAN: Color = Color::Rgb(0x2a, 0xa1, 0x98);
+        const GREEN: Color = Color::Rgb(0x85, 0x99, 0x00);
+        const RED: Color = Color::Rgb(0xdc, 0x32, 0x2f);
+        const MAGENTA: Color = Color::Rgb(0xd3, 0x36, 0x82);
+
+        fn shade(color: Color) -> Color {
+            match color {
+                Color::Yellow | Color::LightYellow => YELLOW,
+                Color::Red | Color::LightRed => RED,
+                Color::Magenta | Color::LightMagenta => MAGENTA,
+                Color::Blue | Color::LightBlue => BLUE,
+                Color::Cyan | Color::LightCyan => CYAN,
+                Color::Green | Color::LightGreen => GREEN,
+                color => color,
+            }
+        }
+
+        Theme {
+            feed: Style::new().fg(YELLOW),
+            folder: Style::new().fg(CYAN),
+            post: Style::new().fg(BASE0),
+            unread: Style::new().fg(BASE1).add_modifier(Modifier::BOLD),
+            star: Style::new().fg(ORANGE),
+            tag: Style::new().fg(VIOLET),
+            label: Style::new().fg(YELLOW),
+            feed_selected: Style::new().bg(BASE02).add_modifier(Modifier::BOLD),
+            selected: Style::new().bg(BASE02).add_modifier(Modifier::BOLD),
+            border: Style::new().fg(BASE01),
+            status: Style::new().fg(BASE1).bg(BASE02),
+            error: Style::new().fg(RED).add_modifier(Modifier::BOLD),
+            text: Style::new().fg(BASE0),
+            heading: Style::new().fg(BASE1).add_modifier(Modifier::BOLD),
+            link: Style::new().fg(BLUE).add_modifier(Modifier::UNDERLINED),
+            link_number: Style::new().fg(BLUE),
+            code: Style::new().fg(GREEN),
+            quote: Style::new().fg(BASE01).add_modifier(Modifier::ITALIC),
+            mark: Style::new().fg(BASE02).bg(YELLOW),
+            gutter: Style::new().fg(BASE01),
+            syntax: Some("Solarized (dark)"),
+            highlights: Some(shade),
+        }
+    }
+
+    fn terminal() -> Theme {
+        let plain = Style::new();
+        Theme {
+            feed: plain,
+            folder: plain.add_modifier(Modifier::ITALIC),
+            post: plain,
+            unread: plain.add_modifier(Modifier::BOLD),
+            star: plain.add_modifier(Modifier::BOLD),
+            tag: plain.add_modifier(Modifier::ITALIC),
+            label: plain.add_modifier(Modifier::BOLD),
+            feed_selected: plain.add_modifier(Modifier::REVERSED),
+            selected: plain.add_modifier(Modifier::REVERSED),
+            border: plain,
+            status: plain.add_modifier(Modifier::REVERSED),
+            error: plain.add_modifier(Modifier::BOLD),
+            text: plain,
+            heading: plain.add_modifier(Modifier::BOLD),
+            link: plain.add_modifier(Modifier::UNDERLINED),
+            link_number: plain,
+            code: plain,
+            quote: plain.add_modifier(Modifier::ITALIC),
+            mark: plain.add_modifier(Modifier::REVERSED),
+            gutter: plain,
+            syntax: None,
+            highlights: None,
+        }
+    }
+
+    /// The style for titles a rule highlights in `color`, unless the theme
+    /// has no colours or the colour is unknown.
+    pub fn highlight(&self, color: &str) -> Option<Style> {
+        let shade = self.highlights?;
+        Some(Style::new().fg(shade(Color::from_str(color).ok()?)))
+    }
+
+    fn style_mut(&mut self, name: &str) -> Option<&mut Style> {
+        Some(match name {
+            "feed" => &mut self.feed,
+            "folder" => &mut self.folder,
+            "post" => &mut self.post,
+            "unread" => &mut self.unread,
+            "star" => &mut self.star,
+            "tag" => &mut self.tag,
+            "label" => &mut self.label,
+            "feed_selected" => &mut self.feed_selected,
+            "selected" => &mut self.selected,
+            "border" => &mut self.border,
+            "status" => &mut self.status,
+            "error" => &mut self.error,
+            "text" => &mut self.text,
+            "heading" => &mut self.heading,
+            "link" => &mut self.link,
+            "link_number" => &mut self.link_number,
+            "code" => &mut self.code,
+            "quote" => &mut self.quote,
+            "mark" => &mut self.mark,
+            "gutter" => &mut self.gutter,
+            _ => return None,
+        })
+    }
+}
+
+impl StyleConfig {
+    /// `style` with the colours and modifiers given here in place of its
+    /// own.
+    fn apply(&self, mut style: Style) -> Result<Style, Box<dyn Error>> {
+        let color =
+            |color: &str| Color::from_str(color).map_err(|_| format!("Invalid colour: {color}"));
+        if let Some(fg) = &self.fg {
+            style.fg = Some(color(fg)?);
+        }
+        if let Some(bg) = &self.bg {
+            style.bg = Some(color(bg)?);
+        }
+        if let Some(modifiers) = &self.modifiers {
+            style.add_modifier = Modifier::empty();
+            style.sub_modifier = Modifier::empty();
+            for modifier in modifiers {
+                style = style.add_modifier(match modifier.as_str() {
+                    "bold" => Modifier::BOLD,
+                    "dim" => Modifier::DIM,
+                    "italic" => Modifier::ITALIC,
+                    "underlined" => Modifier::UNDERLINED,
+                    "reversed" => Modifier::REVERSED,
+                    "crossed_out" => Modifier::CROSSED_OUT,
+                    _ => return Err(format!("Unknown modifier: {modifier}").into()),
+                });
+            }
+        }
+        Ok(style)
+    }
+}
@@ -1,143 +1,564 @@
+use chrono::Local;
 use ratatui::{
     Frame,
-    layout::{Constraint, Direction, Layout},
-    style::{Color, Modifier, Style},
-    text::{Line, Span},
+    layout::{Constraint, Direction, Layout, Rect},
+    text::{Line, Span, Text},
     widgets::{
-        Block, BorderType, Borders, List, ListItem, Padding, Paragraph, Scrollbar,
-        ScrollbarOrientation, ScrollbarState, Wrap,
+        Block, BorderType, Borders, Clear, List, ListItem, Padding, Paragraph, Scrollbar,
+        ScrollbarOrientation, ScrollbarState,
     },
 };
+use ratatui_image::StatefulImage;
 use std::error::Error;
 
 use crate::{
-    app::{App, Screen},
-    styling::html_to_ratatui,
+    app::{App, LinkPicker, PromptKind, Screen, Selection},
+    feed::Post,
+    keymap::Context,
+    messages::Level,
+    styling::{self, ArticleCache},
+    theme::Theme,
 };
 
+/// Where the last frame drew each part, for telling what the mouse is
+/// over. Parts that weren't drawn are empty.
+#[derive(Default, Clone, Copy)]
+pub struct Areas {
+    pub menu: Rect,
+    pub posts: Rect,
+    /// The reader with its border; the scrollbar is on the right edge.
+    pub reader: Rect,
+    /// The article's text, inside the border and padding.
+    pub article: Rect,
+    /// Rows the post header takes above the article.
+    pub header_rows: u16,
+    pub links: Rect,
+}
+
 pub fn ui(frame: &mut Frame, app: &mut App) {
-    let chunks = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([Constraint::Max(app.text_width), Constraint::Min(1)])
+    app.view.areas = Areas::default();
+    let prompt_rows = u16::from(app.prompt.is_some());
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(1),
+            Constraint::Length(1),
+            Constraint::Length(prompt_rows),
+        ])
         .split(frame.area());
+    let area = rows[0];
+    status_bar(frame, app, rows[1]);
+    if let Some(prompt) = &app.prompt {
+        let label = match prompt.kind {
+            PromptKind::Tag => "tags (-tag removes): ",
+            PromptKind::Command => ":",
+        };
+        frame.render_widget(
+            Paragraph::new(Line::from(vec![
+                Span::styled(label, app.theme.label),
+                Span::raw(prompt.text.clone()),
+            ])),
+            rows[2],
+        );
+    }
 
-    match &app.current_screen {
-        Screen::MainMenu => {
-            let mut feed_titles = Vec::<ListItem>::new();
-
-            app.index.meta.iter().for_each(|meta| {
-                feed_titles.push(ListItem::new(Line::from(Span::styled(
-                    format!("{}", meta.title),
-                    Style::default().fg(Color::Yellow),
-                ))));
-            });
-
-            let feed_list = List::new(feed_titles)
-                .highlight_style(Style::new().bg(Color::Green).add_modifier(Modifier::BOLD));
-
-            frame.render_stateful_widget(feed_list, chunks[0], &mut app.index.state);
-
-            let mut post_titles = Vec::<ListItem>::new();
-            match app.index.state.selected() {
-                Some(i) => {
-                    for post in &app.feeds[i].posts {
-                        post_titles.push(ListItem::new(Line::from(Span::styled(
-                            format!("{}", post.title),
-                            Style::default().fg(Color::Green),
-                        ))));
-                    }
-                    let posts_list = List::new(post_titles)
-                        .highlight_style(Style::new().bg(Color::Red).add_modifier(Modifier::BOLD));
-                    frame.render_widget(posts_list, chunks[1]);
-                }
-                None => {
-                    frame.render_widget(Paragraph::new("No feed selected."), chunks[1]);
-                }
+    let panes = app.layout.split(area, &app.current_screen);
+
+    if let Some(pane) = panes.feeds {
+        app.view.areas.menu = pane;
+        frame.render_stateful_widget(feed_list(app), pane, &mut app.view.menu);
+    }
+    if let Some(pane) = panes.posts {
+        app.view.areas.posts = pane;
+        let posts_list = post_list(app);
+        let listed = !matches!(app.current_screen, Screen::MainMenu);
+        match app.post_state() {
+            Some(state) if listed => frame.render_stateful_widget(posts_list, pane, state),
+            Some(_) => frame.render_widget(posts_list, pane),
+            None => frame.render_widget(Paragraph::new("No feed selected."), pane),
+        }
+    }
+    match app.current_screen {
+        Screen::MainMenu => app.view.page_rows = panes.feeds.unwrap_or_default().height,
+        Screen::FeedMenu => app.view.page_rows = panes.posts.unwrap_or_default().height,
+        _ => {}
+    }
+    if let Some(pane) = panes.article {
+        let selected = app.selected_post().is_some();
+        match app.current_screen {
+            Screen::Reader => article(frame, app, pane, false),
+            // Three panes show the selected post before it is opened.
+            Screen::FeedMenu if selected => article(frame, app, pane, true),
+            _ => frame.render_widget(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_type(BorderType::Rounded)
+                    .border_style(app.theme.border),
+                pane,
+            ),
+        }
+        if let Some(picker) = &mut app.picker {
+            app.view.areas.links = link_picker(frame, picker, &app.theme, pane);
+        }
+    }
+
+    if app.help
+        && let Some(context) = app.key_context()
+    {
+        help(frame, app, context, area);
+    }
+    if app.log {
+        message_log(frame, app, area);
+    }
+}
+
+/// The bottom line: where the selection is, the latest message, and the
+/// unread count with the refresh in progress or the last one.
+fn status_bar(frame: &mut Frame, app: &mut App, area: Rect) {
+    let theme = app.theme;
+    let mut left = Vec::new();
+    let screen = match app.current_screen {
+        Screen::MainMenu => "feeds",
+        Screen::FeedMenu => "posts",
+        Screen::Reader => "reader",
+        Screen::Exiting => "quit",
+    };
+    left.push(Span::styled(
+        format!(" {screen} "),
+        theme.status.patch(theme.label),
+    ));
+
+    let entries = app.feeds.len() + app.folders.len();
+    let entry = app.view.menu.selected();
+    if let Some(selection) = app.selection() {
+        let title = match selection {
+            Selection::Feed(i) => app.feeds[i].meta.title.clone(),
+            Selection::Folder(i) => app.folders[i].name.clone(),
+        };
+        left.push(Span::raw(format!("{title} ")));
+    }
+    match app.current_screen {
+        Screen::FeedMenu | Screen::Reader => {
+            let posts = app.posts().len();
+            let post = app.post_state().and_then(|state| state.selected());
+            if let Some(post) = post.filter(|_| posts > 0) {
+                left.push(Span::raw(format!("{}/{posts} ", post.min(posts - 1) + 1)));
+            }
+        }
+        _ => {
+            if let Some(entry) = entry.filter(|_| entries > 0) {
+                left.push(Span::raw(format!(
+                    "{}/{entries} ",
+                    entry.min(entries - 1) + 1
+                )));
             }
         }
-        Screen::FeedMenu => {
-            let mut feed_titles = Vec::<ListItem>::new();
-
-            app.index.meta.iter().for_each(|meta| {
-                feed_titles.push(ListItem::new(Line::from(Span::styled(
-                    format!("{}", meta.title),
-                    Style::default().fg(Color::Yellow),
-                ))));
-            });
-
-            let feed_list = List::new(feed_titles)
-                .highlight_style(Style::new().bg(Color::Green).add_modifier(Modifier::BOLD));
-
-            frame.render_stateful_widget(feed_list, chunks[0], &mut app.index.state);
-
-            let mut post_titles = Vec::<ListItem>::new();
-            match app.index.state.selected() {
-                Some(i) => {
-                    for post in &app.feeds[i].posts {
-                        post_titles.push(ListItem::new(Line::from(Span::styled(
-                            format!("{}", post.title),
-                            Style::default().fg(Color::Green),
-                        ))));
+    }
+
+    let message = if matches!(app.current_screen, Screen::Exiting) {
+        Some(Span::raw("Quit russ? (y/n)"))
+    } else {
+        app.messages.current().map(|message| match message.level {
+            Level::Info => Span::raw(message.text.clone()),
+            Level::Error => Span::styled(message.text.clone(), theme.status.patch(theme.error)),
+        })
+    };
+    left.extend(message);
+
+    let unread = app
+        .feeds
+        .iter()
+        .flat_map(|feed| &feed.posts)
+        .filter(|post| !post.read && !post.hidden)
+        .count();
+    let mut right = format!("{unread} unread");
+    if let Some(refresh) = &app.refreshing {
+        right += &format!(" | refreshing {}/{}", refresh.done, refresh.feeds.len());
+    } else if let Some(fetched_at) = app.feeds.iter().filter_map(|f| f.meta.fetched_at).max() {
+        let fetched_at = fetched_at.with_timezone(&Local);
+        let format = if fetched_at.date_naive() == Local::now().date_naive() {
+            "%H:%M"
+        } else {
+            "%Y-%m-%d %H:%M"
+        };
+        right += &format!(" | refreshed {}", fetched_at.format(format));
+    }
+    right.push(' ');
+
+    let width = u16::try_from(right.chars().count()).unwrap_or(u16::MAX);
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Min(0), Constraint::Length(width)])
+        .split(area);
+    frame.render_widget(
+        Paragraph::new(Line::from(left)).style(theme.status),
+        columns[0],
+    );
+    frame.render_widget(Paragraph::new(right).style(theme.status), columns[1]);
+}
+
+/// Popup listing everything reported this session, newest at the bottom.
+fn message_log(frame: &mut Frame, app: &App, area: Rect) {
+    let messages = app.messages.all();
+    let width = area.width.saturating_sub(8);
+    let height = (messages.len().max(1) as u16 + 2).min(area.height.saturating_sub(2));
+    let popup = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    };
+
+    let mut lines: Vec<Line> = messages
+        .iter()
+        .map(|message| {
+            let style = match message.level {
+                Level::Info => app.theme.text,
+                Level::Error => app.theme.error,
+            };
+            Line::from(vec![
+                Span::styled(
+                    message.time.format("%H:%M:%S ").to_string(),
+                    app.theme.gutter,
+                ),
+                Span::styled(message.text.clone(), style),
+            ])
+        })
+        .collect();
+    if lines.is_empty() {
+        lines.push(Line::styled("No messages yet.", app.theme.gutter));
+    }
+    // Keep the newest in view.
+    let shown = usize::from(height.saturating_sub(2));
+    let lines = lines.split_off(lines.len().saturating_sub(shown));
+
+    let paragraph = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .border_style(app.theme.border)
+            .title(Line::from("Messages"))
+            .title_bottom(Line::from("any key closes")),
+    );
+    frame.render_widget(Clear, popup);
+    frame.render_widget(paragraph, popup);
+}
+
+/// Draws the post in the reader, or the selected one as a `preview` that
+/// isn't scrolled and doesn't take the keys.
+fn article(frame: &mut Frame, app: &mut App, area: Rect, preview: bool) {
+    if let Some(images) = &mut app.images {
+        images.receive();
+    }
+    match Reader::new(app, area, preview) {
+        Ok(mut r) => {
+            frame.render_widget(r.paragraph, area);
+            frame.render_stateful_widget(r.scrollbar, area, &mut r.scrollbar_state);
+            if let Some(images) = &mut app.images {
+                for (url, area) in r.images {
+                    images.request(&url);
+                    if let Some(protocol) = images.get(&url) {
+                        frame.render_stateful_widget(StatefulImage::new(), area, protocol);
                     }
-                    let posts_list = List::new(post_titles)
-                        .highlight_style(Style::new().bg(Color::Red).add_modifier(Modifier::BOLD));
-                    frame.render_stateful_widget(posts_list, chunks[1], &mut app.feeds[i].state);
-                }
-                None => {
-                    frame.render_widget(Paragraph::new("No feed selected."), chunks[1]);
                 }
             }
         }
-        Screen::Reader => {
-            match Reader::new(app) {
-                Ok(mut r) => {
-                    frame.render_widget(r.paragraph, chunks[1]);
-                    frame.render_stateful_widget(r.scrollbar, chunks[1], &mut r.scrollbar_state);
-                }
-                Err(e) => println!("error: {}", e),
-            };
+        Err(e) => frame.render_widget(
+            Paragraph::new(Span::styled(e.to_string(), app.theme.error)).block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_type(BorderType::Rounded)
+                    .border_style(app.theme.border),
+            ),
+            area,
+        ),
+    };
+}
+
+/// Popup listing the keys bound in `context`.
+fn help(frame: &mut Frame, app: &App, context: Context, area: Rect) {
+    let bindings = app.keymap.help(context);
+    let width = bindings
+        .iter()
+        .map(|(keys, _)| keys.len())
+        .max()
+        .unwrap_or(0);
+    let lines: Vec<Line> = bindings
+        .iter()
+        .map(|(keys, action)| {
+            Line::from(vec![
+                Span::styled(format!("{keys:<width$}  "), app.theme.label),
+                Span::raw(format!("{:<13}", action.name())),
+                Span::styled(action.description(), app.theme.gutter),
+            ])
+        })
+        .collect();
+
+    let popup_width = (width as u16 + 60).min(area.width.saturating_sub(4));
+    let height = (lines.len() as u16 + 2).min(area.height.saturating_sub(2));
+    let popup = Rect {
+        x: area.x + (area.width - popup_width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width: popup_width,
+        height,
+    };
+    let paragraph = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .border_style(app.theme.border)
+            .title(Line::from(format!("Keys: {}", context.name())))
+            .title_bottom(Line::from("any key closes")),
+    );
+
+    frame.render_widget(Clear, popup);
+    frame.render_widget(paragraph, popup);
+}
+
+/// Popup over the reader listing the article's numbered links. Returns
+/// where it was drawn.
+fn link_picker(frame: &mut Frame, picker: &mut LinkPicker, theme: &Theme, area: Rect) -> Rect {
+    let width = area.width.saturating_sub(8);
+    let height = (picker.links.len() as u16 + 2).min(area.height.saturating_sub(2));
+    let popup = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    };
+
+    let digits = picker.links.len().to_string().len();
+    let items: Vec<ListItem> = picker
+        .links
+        .iter()
+        .enumerate()
+        .map(|(i, link)| {
+            ListItem::new(Line::from(vec![
+                Span::styled(
+                    format!("{:<w$} ", format!("[{}]", i + 1), w = digits + 2),
+                    theme.link_number,
+                ),
+                Span::raw(link.clone()),
+            ]))
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .border_style(theme.border)
+                .title(Line::from("Links"))
+                .title_bottom(Line::from("enter open | y copy | a subscribe | esc close")),
+        )
+        .highlight_style(theme.selected);
+
+    frame.render_widget(Clear, popup);
+    frame.render_stateful_widget(list, popup, &mut picker.state);
+    popup
+}
+
+/// Feeds followed by smart folders and tags, as listed in the main menu.
+fn feed_list(app: &App) -> List<'static> {
+    let mut feed_titles = Vec::<ListItem>::new();
+
+    // The feeds as selection and the reader index them; the index may lag
+    // behind during a refresh.
+    app.feeds.iter().for_each(|feed| {
+        feed_titles.push(ListItem::new(Line::from(Span::styled(
+            feed.meta.title.clone(),
+            app.theme.feed,
+        ))));
+    });
+    app.folders.iter().for_each(|folder| {
+        feed_titles.push(ListItem::new(Line::from(Span::styled(
+            format!("{} ({})", folder.name, folder.posts.len()),
+            app.theme.folder,
+        ))));
+    });
+
+    List::new(feed_titles).highlight_style(app.theme.feed_selected)
+}
+
+fn post_list(app: &App) -> List<'static> {
+    let mut post_titles = Vec::<ListItem>::new();
+
+    for (ch, p) in app.posts() {
+        let post = &app.feeds[ch].posts[p];
+        let mut style = app.theme.post;
+        if !post.read {
+            style = style.patch(app.theme.unread);
         }
-        _ => {}
+        if let Some(highlight) = post
+            .highlight
+            .as_deref()
+            .and_then(|c| app.theme.highlight(c))
+        {
+            style = style.patch(highlight);
+        }
+        let marker = if post.starred { "* " } else { "  " };
+        let mut line = vec![
+            Span::styled(marker, app.theme.star),
+            Span::styled(post.title.clone(), style),
+        ];
+        for tag in &post.tags {
+            line.push(Span::styled(format!(" #{tag}"), app.theme.tag));
+        }
+        post_titles.push(ListItem::new(Line::from(line)));
     }
+
+    List::new(post_titles).highlight_style(app.theme.selected)
 }
 
 pub struct Reader<'a> {
     paragraph: Paragraph<'a>,
     scrollbar: Scrollbar<'a>,
     scrollbar_state: ScrollbarState,
+    /// Images that are wholly in view, and where to draw them.
+    images: Vec<(String, Rect)>,
 }
 
+/// Least horizontal padding inside the reader's border, on each side.
+const READER_PADDING: u16 = 2;
+
 impl Reader<'_> {
-    fn new(app: &App) -> Result<Reader<'_>, Box<dyn Error>> {
-        let ch = app.index.state.selected().unwrap();
-        let p = app.feeds[ch].state.selected().unwrap();
+    fn new(app: &mut App, area: Rect, preview: bool) -> Result<Reader<'static>, Box<dyn Error>> {
+        let (ch, p) = app.selected_post().ok_or("No post selected")?;
+        let post = &app.feeds[ch].posts[p];
 
-        let text = html_to_ratatui(app.feeds[ch].posts[p].content.as_bytes());
-        let title = app.feeds[ch].posts[p].title.as_str();
-        let mut scroll = app.feeds[ch].posts[p].scroll;
+        // Inside the border and padding, with the text centred when the
+        // reader is wider than the text may be.
+        let room = area.width.saturating_sub(2);
+        let width = room
+            .saturating_sub(2 * READER_PADDING)
+            .min(app.layout.max_text_width());
+        let padding = (room - width) / 2;
+        let inner = Rect {
+            x: area.x + 1 + padding,
+            y: area.y + 1,
+            width,
+            height: area.height.saturating_sub(2),
+        };
+        let (content, content_type) = post.displayed_content(app.view.original);
+        let cached = ArticleCache::get(
+            &mut app.article,
+            &app.feeds[ch].meta.id,
+            post.key(),
+            header(&app.feeds[ch].meta.title, post, &app.theme),
+            content,
+            &styling::Options {
+                width: inner.width,
+                base: &post.link,
+                hscroll: app.view.scroll.1,
+                images: app.images.is_some(),
+                plain: app.view.plain,
+                content_type,
+                theme: app.theme,
+                enclosures: &post.images,
+            },
+        );
+        let header_rows = cached.header.len();
+        let rows = cached.rows();
+        let title = match (&post.extracted, app.view.original) {
+            (Some(_), false) => format!("{} (full article)", post.title),
+            _ => post.title.clone(),
+        };
+        let scroll_max =
+            u16::try_from(rows.saturating_sub(usize::from(inner.height))).unwrap_or(u16::MAX);
+        let scroll = if preview {
+            (0, app.view.scroll.1)
+        } else {
+            // The reader is resized or the article changes between key
+            // presses, so clamp again here.
+            app.view.page_rows = inner.height;
+            app.view.areas.reader = area;
+            app.view.areas.article = inner;
+            app.view.areas.header_rows = u16::try_from(header_rows).unwrap_or(u16::MAX);
+            app.view.scroll_max = scroll_max;
+            app.view.hscroll_max = u16::try_from(cached.article.hscroll_max).unwrap_or(u16::MAX);
+            app.view.scroll_to(app.view.scroll.0);
+            app.view.scroll
+        };
 
-        let paragraph = Paragraph::new(text)
-            .style(Style::new().fg(Color::DarkGray))
+        let paragraph = Paragraph::new(cached.window(scroll.0.into(), inner.height.into()))
+            .style(app.theme.text)
             .block(
                 Block::default()
                     .borders(Borders::ALL)
+                    .border_style(app.theme.border)
                     .title(Line::from(title))
                     .border_type(BorderType::Rounded)
-                    .padding(Padding::symmetric(8, 0)),
-            )
-            .wrap(Wrap { trim: false }).scroll(scroll);
-
-        scroll.0 = scroll.0.clamp(0, paragraph.line_count(app.text_width) as u16);
+                    .padding(Padding::new(padding, room - width - padding, 0, 0)),
+            );
 
         let scrollbar_state =
-            ScrollbarState::new(paragraph.line_count(app.text_width)).position(scroll.0.into());
+            ScrollbarState::new(usize::from(scroll_max)).position(scroll.0.into());
+
+        let images = cached
+            .article
+            .images
+            .iter()
+            .filter_map(|slot| {
+                let top = (header_rows + slot.line).checked_sub(usize::from(scroll.0))?;
+                (top + slot.height <= usize::from(inner.height)).then(|| {
+                    let area = Rect {
+                        x: inner.x + slot.column as u16,
+                        y: inner.y + top as u16,
+                        width: slot.width as u16,
+                        height: slot.height as u16,
+                    };
+                    (slot.url.clone(), area)
+                })
+            })
+            .collect();
 
         Ok(Reader {
-            paragraph: paragraph,
+            paragraph,
             scrollbar: Scrollbar::new(ScrollbarOrientation::VerticalRight)
                 .begin_symbol(Some("^"))
                 .end_symbol(Some("v")),
-            scrollbar_state: scrollbar_state,
+            scrollbar_state,
+            images,
         })
     }
 }
+
+/// Metadata lines shown above the article: feed, author, date, categories
+/// and tags.
+fn header(feed_title: &str, post: &Post, theme: &Theme) -> Text<'static> {
+    let label = theme.label;
+    let mut text = Text::default();
+
+    let mut line = vec![Span::styled(feed_title.to_string(), label)];
+    if let Some(byline) = post.byline() {
+        line.push(Span::raw(format!(" | {byline}")));
+    }
+    if let Some(date) = post.published {
+        line.push(Span::raw(format!(" | {}", date.format("%Y-%m-%d %H:%M"))));
+    }
+    text.push_line(Line::from(line));
+
+    if !post.categories.is_empty() {
+        text.push_line(Line::from(vec![
+            Span::styled("Categories: ", label),
+            Span::raw(post.categories.join(", ")),
+        ]));
+    }
+    if !post.tags.is_empty() {
+        text.push_line(Line::from(vec![
+            Span::styled("Tags: ", label),
+            Span::styled(
+                post.tags
+                    .iter()
+                    .map(|t| format!("#{t}"))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                theme.tag,
+            ),
+        ]));
+    }
+    text.push_line(Line::default());
+
+    text
+}